@@ -0,0 +1,91 @@
+//! RPC interface for previewing exchange orders.
+
+use std::{convert::{TryFrom, TryInto}, sync::Arc};
+
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use parity_scale_codec::Codec;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_rpc::number::NumberOrHex;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+use pallet_exchange_rpc_runtime_api::{OrderSide, PairIndex, Price, Quote};
+pub use pallet_exchange_rpc_runtime_api::ExchangeApi as ExchangeRuntimeApi;
+
+#[rpc]
+pub trait ExchangeApi<BlockHash, Balance> {
+	/// Expected fill of an order taking liquidity, without submitting it. Balances are
+	/// passed as numbers or hex strings so they survive JSON number precision.
+	#[rpc(name = "exchange_quote")]
+	fn quote(
+		&self,
+		pair: PairIndex,
+		side: OrderSide,
+		volume: NumberOrHex,
+		limit: Option<Price>,
+		at: Option<BlockHash>) -> Result<Quote<NumberOrHex>>;
+}
+
+/// Exchange RPC methods, served from the runtime API.
+pub struct Exchange<C, B> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<B>,
+}
+
+impl<C, B> Exchange<C, B> {
+	pub fn new(client: Arc<C>) -> Self {
+		Exchange { client, _marker: Default::default() }
+	}
+}
+
+/// Error codes returned by the exchange RPC.
+pub enum Error {
+	/// The runtime call failed.
+	RuntimeError,
+}
+
+impl From<Error> for i64 {
+	fn from(e: Error) -> i64 {
+		match e {
+			Error::RuntimeError => 1,
+		}
+	}
+}
+
+impl<C, Block, Balance> ExchangeApi<<Block as BlockT>::Hash, Balance> for Exchange<C, Block>
+where
+	Block: BlockT,
+	C: Send + Sync + 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+	C::Api: ExchangeRuntimeApi<Block, Balance>,
+	Balance: Codec + TryFrom<NumberOrHex> + Into<NumberOrHex> + Send + Sync + 'static,
+{
+	fn quote(
+		&self,
+		pair: PairIndex,
+		side: OrderSide,
+		volume: NumberOrHex,
+		limit: Option<Price>,
+		at: Option<<Block as BlockT>::Hash>) -> Result<Quote<NumberOrHex>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		let volume: Balance = volume.try_into().map_err(|_| RpcError {
+			code: ErrorCode::InvalidParams,
+			message: "Volume does not fit into the balance type.".into(),
+			data: None,
+		})?;
+
+		let quote = api.quote(&at, pair, side, volume, limit).map_err(|e| RpcError {
+			code: ErrorCode::ServerError(Error::RuntimeError.into()),
+			message: "Unable to quote order.".into(),
+			data: Some(format!("{:?}", e).into()),
+		})?;
+		Ok(Quote {
+			filled: quote.filled.into(),
+			received: quote.received.into(),
+			average_price: quote.average_price,
+			fee: quote.fee.into(),
+			unfilled: quote.unfilled.into(),
+		})
+	}
+}
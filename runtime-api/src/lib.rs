@@ -0,0 +1,21 @@
+//! Runtime API for previewing exchange orders.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use parity_scale_codec::Codec;
+
+pub use pallet_exchange::{OrderSide, PairIndex, Price, Quote};
+
+sp_api::decl_runtime_apis! {
+	pub trait ExchangeApi<Balance> where
+		Balance: Codec,
+	{
+		/// Expected fill of an order of `volume` taking liquidity on `side` of `pair`,
+		/// bounded by `limit`. Nothing is written to storage.
+		fn quote(
+			pair: PairIndex,
+			side: OrderSide,
+			volume: Balance,
+			limit: Option<Price>) -> Quote<Balance>;
+	}
+}
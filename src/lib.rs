@@ -6,34 +6,100 @@ use frame_support::{
 	decl_module, 
 	decl_storage, 
 	ensure, 
-	//dispatch::DispatchResult,
+	Blake2_128Concat,
+	StorageHasher,
+	transactional,
+	dispatch::{DispatchError, DispatchResult},
+	storage::{IterableStorageDoubleMap, IterableStorageMap, migration},
+	weights::Weight,
 	traits::{
 		Currency, 
-		//Get,
+		BalanceStatus,
+		EnsureOrigin,
+		Get,
 		ReservableCurrency, 
 		ExistenceRequirement::AllowDeath
 	},
 };
 use frame_system::{
 	self as system, 
-	ensure_signed,
-	ensure_root
+	ensure_signed
 };
 use parity_scale_codec::{
 	Decode, 
 	Encode
 };
-use sp_std::prelude::*;
+use sp_runtime::{
+	FixedPointNumber,
+	FixedU128,
+	RuntimeDebug,
+	helpers_128bit,
+	Permill,
+	traits::{Hash, One, SaturatedConversion, Saturating, Zero}
+};
+use sp_std::{
+	collections::btree_map::BTreeMap,
+	convert::TryInto,
+	marker::PhantomData,
+	prelude::*
+};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
 
 use pallet_token as Token;
 
 
+#[cfg(test)]
+mod mock;
+
 #[cfg(test)]
 mod tests;
 
 pub trait Trait: system::Trait + pallet_token::Trait   {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 	type Currency: ReservableCurrency<Self::AccountId>;
+	/// Maximum number of expired orders swept in a single block. Treated as at least one.
+	type MaxExpiriesPerBlock: Get<u32>;
+	/// Weight reserved each block for crossing books and triggering conditional orders
+	/// in `on_finalize`.
+	type MatchingWeightBudget: Get<Weight>;
+	/// Maximum number of resting orders an incoming order fills against.
+	type MaxFillsPerOrder: Get<u32>;
+	/// Maximum number of orders linked in one order group.
+	type MaxGroupLegs: Get<u32>;
+	/// Number of client order ids remembered per account once their orders are closed,
+	/// most recent first. Treated as at least one.
+	type MaxClientOrderIds: Get<u32>;
+	/// Native coin held against each sealed order commitment.
+	type CommitDeposit: Get<BalanceOf<Self>>;
+	/// Blocks after a commitment before it can be revealed.
+	type RevealDelay: Get<Self::BlockNumber>;
+	/// Blocks during which a commitment can be revealed.
+	type RevealWindow: Get<Self::BlockNumber>;
+	/// Share of the deposit forfeited by a commitment that is never revealed.
+	type CommitPenalty: Get<Permill>;
+	/// Maximum number of unrevealed commitments refunded in a single block. Treated as
+	/// at least one.
+	type MaxCommitmentExpiriesPerBlock: Get<u32>;
+	/// Maximum number of pairs a routed swap may go through.
+	type MaxRouteLength: Get<u32>;
+	/// Maximum number of orders refunded per block while pairs are being delisted.
+	/// Treated as at least one.
+	type MaxDelistRefundsPerBlock: Get<u32>;
+	/// Assets that pairs may trade, normally `TokenAssets` over `pallet_token`.
+	type Assets: AssetRegistry;
+	/// Origin allowed to create pairs.
+	type CreatePairOrigin: EnsureOrigin<Self::Origin>;
+	/// Origin allowed to set fees, market parameters and matching modes.
+	type ParamsOrigin: EnsureOrigin<Self::Origin>;
+	/// Origin allowed to pause, resume and delist pairs.
+	type PauseOrigin: EnsureOrigin<Self::Origin>;
+	/// Origin allowed to set the operation and vault accounts.
+	type AccountsOrigin: EnsureOrigin<Self::Origin>;
+	/// Bond reserved through `Currency` from whoever proposes a new pair.
+	type ListingBond: Get<ListingBalanceOf<Self>>;
+	/// Origin allowed to approve or reject pair listing proposals.
+	type ListingApproveOrigin: EnsureOrigin<Self::Origin>;
 	// type AccountOperation: Get<Self::AccountId>;
 	// type AccountVault: Get<Self::AccountId>;
 
@@ -45,42 +111,165 @@ pub type PairIndex = u128;
 pub type TradeIndex = u128;
 pub type BuyOrderIndex = u128;
 pub type SellOrderIndex = u128;
-pub type PairNativeIndex = u128;
-pub type TradeNativeIndex = u128;
-pub type BuyOrderNativeIndex = u128;
-pub type SellOrderNativeIndex = u128;
 pub type TokenIndex = u32;
+pub type ConditionalOrderIndex = u128;
+pub type OrderGroupIndex = u128;
+pub type ClientOrderId = u128;
+pub type CommitmentIndex = u128;
+pub type ListingIndex = u128;
+/// Base paid per unit of target.
+pub type Price = FixedU128;
+
+/// Asset on either side of a pair.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum AssetId {
+	/// The chain's native coin, held through `Currency`.
+	Native,
+	/// A token of `pallet_token`.
+	Token(TokenIndex)
+}
+
+impl Default for AssetId {
+	fn default() -> Self {
+		AssetId::Native
+	}
+}
+
+/// Display metadata of an asset, kept on each pair so prices can be normalised.
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct AssetMetadata {
+	pub decimals: u8,
+	pub symbol: Vec<u8>
+}
+
+/// Lookup of the assets a pair may trade.
+pub trait AssetRegistry {
+	/// Metadata of `asset`, or `None` if there is no such asset.
+	fn metadata(asset: AssetId) -> Option<AssetMetadata>;
+	/// Whether new pairs may be created for `asset`.
+	fn is_enabled(asset: AssetId) -> bool;
+}
+
+/// `AssetRegistry` backed by `pallet_token`, with `NativeMetadata` describing the
+/// native coin. Tokens that are not active in `pallet_token` are disabled.
+pub struct TokenAssets<T, NativeMetadata>(PhantomData<(T, NativeMetadata)>);
+
+impl<T: pallet_token::Trait, NativeMetadata: Get<AssetMetadata>> AssetRegistry for TokenAssets<T, NativeMetadata> {
+	fn metadata(asset: AssetId) -> Option<AssetMetadata> {
+		match asset {
+			AssetId::Native => Some(NativeMetadata::get()),
+			AssetId::Token(token) => <Token::Module<T>>::token(token).map(|info| AssetMetadata {
+				decimals: info.decimals,
+				symbol: info.symbol
+			}),
+		}
+	}
+
+	fn is_enabled(asset: AssetId) -> bool {
+		match asset {
+			AssetId::Native => true,
+			AssetId::Token(token) => <Token::Module<T>>::token(token).map_or(false, |info| info.active),
+		}
+	}
+}
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum OrderSide {
+	Buy,
+	Sell
+}
+
+impl OrderSide {
+	pub fn opposite(&self) -> OrderSide {
+		match self {
+			OrderSide::Buy => OrderSide::Sell,
+			OrderSide::Sell => OrderSide::Buy,
+		}
+	}
+}
+
+/// How long an order stays on the book.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum TimeInForce {
+	/// Rests until filled, cancelled or expired.
+	GoodTillCancelled,
+	/// Fills what it can at placement and refunds the rest.
+	ImmediateOrCancel,
+	/// Fills completely at placement or is rejected.
+	FillOrKill,
+	/// Rests without taking liquidity; rejected if it would cross the book.
+	PostOnly
+}
+
+impl Default for TimeInForce {
+	fn default() -> Self {
+		TimeInForce::GoodTillCancelled
+	}
+}
+
+impl TimeInForce {
+	pub fn is_immediate(&self) -> bool {
+		*self == TimeInForce::ImmediateOrCancel || *self == TimeInForce::FillOrKill
+	}
+}
+
+/// When crossing orders on a pair are matched.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum MatchingMode {
+	/// Orders rest on placement and the book is crossed in `on_finalize`.
+	Batch,
+	/// Orders match against the book on placement and only the remainder rests.
+	Continuous,
+	/// Orders rest on placement and the book clears once per block as a
+	/// uniform-price auction. Orders that would trade immediately are refused.
+	Auction
+}
+
+impl Default for MatchingMode {
+	fn default() -> Self {
+		MatchingMode::Batch
+	}
+}
+
+/// Location of a resting order.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct OrderRef {
+	side: OrderSide,
+	pair: PairIndex,
+	order_id: BuyOrderIndex
+}
 
 type AccountIdOf<T> = <T as system::Trait>::AccountId;
 type BalanceOf<T> = <<T as pallet_token::Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
 
-type PairInfoOf<T> = PairInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
-type PairNativeInfoOf<T> = PairNativeInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
+type PairInfoOf<T> = PairInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type ConditionalOrderInfoOf<T> = ConditionalOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type OrderGroupInfoOf<T> = OrderGroupInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
+type CommitmentInfoOf<T> = CommitmentInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber, <T as system::Trait>::Hash>;
 type TradeInfoOf<T> = TradeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
-type TradeNativeInfoOf<T> = TradeNativeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 type BuyOrderInfoOf<T> = BuyOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
-type BuyOrderNativeInfoOf<T> = BuyOrderNativeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 type SellOrderInfoOf<T> = SellOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
-type SellOrderNativeInfoOf<T> = SellOrderNativeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
-
+type ListingBalanceOf<T> = <<T as Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
+type ListingProposalOf<T> = ListingProposal<AccountIdOf<T>, ListingBalanceOf<T>, <T as system::Trait>::BlockNumber>;
 
-#[derive(Encode, Decode, Default, PartialEq, Eq)]
-#[cfg_attr(feature = "std", derive(Debug))]
-pub struct PairInfo<AccountId, BlockNumber> {
-	base: u32,
-	target: u32,
-	banker: AccountId,
-	active: bool,
-	created: BlockNumber
-}
 
 #[derive(Encode, Decode, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct PairNativeInfo<AccountId, BlockNumber> {
-	target: u32,
+pub struct PairInfo<AccountId, Balance, BlockNumber> {
+	base: AssetId,
+	target: AssetId,
 	banker: AccountId,
 	active: bool,
-	created: BlockNumber
+	created: BlockNumber,
+	/// Order ratios must be a multiple of this; zero allows any ratio.
+	tick_size: Price,
+	/// Order volumes in target must be a multiple of this; zero allows any volume.
+	lot_size: Balance,
+	/// Smallest order value in base.
+	min_notional: Balance,
+	base_metadata: AssetMetadata,
+	target_metadata: AssetMetadata
 }
 
 
@@ -91,43 +280,131 @@ pub struct BuyOrderInfo<AccountId, Balance, BlockNumber> {
 	pair: PairIndex,
 	buyer: AccountId,
 	volume: Balance,
-	ratio: Balance,
-	created: BlockNumber
+	ratio: Price,
+	display: Balance,
+	reserve: Balance,
+	created: BlockNumber,
+	sequence: u64,
+	expires_at: Option<BlockNumber>,
+	client_id: Option<ClientOrderId>
 }
 
 #[derive(Encode, Decode, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct BuyOrderNativeInfo<AccountId, Balance, BlockNumber> {
-	order_id: BuyOrderNativeIndex,
-	pair: PairNativeIndex,
-	buyer: AccountId,
+pub struct SellOrderInfo<AccountId, Balance, BlockNumber> {
+	order_id: SellOrderIndex,
+	pair: PairIndex,
+	seller: AccountId,
 	volume: Balance,
-	ratio: Balance,
-	created: BlockNumber
+	ratio: Price,
+	display: Balance,
+	reserve: Balance,
+	created: BlockNumber,
+	sequence: u64,
+	expires_at: Option<BlockNumber>,
+	client_id: Option<ClientOrderId>
 }
 
-#[derive(Encode, Decode, Default, PartialEq, Eq)]
+
+/// Stop order held outside the book until the last trade price crosses `trigger`.
+/// Releases as a limit order at `limit`, or as a market order when `limit` is unset.
+#[derive(Encode, Decode, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct SellOrderInfo<AccountId, Balance, BlockNumber> {
-	order_id: SellOrderNativeIndex,
-	pair: PairNativeIndex,
-	seller: AccountId,
+pub struct ConditionalOrderInfo<AccountId, Balance, BlockNumber> {
+	order_id: ConditionalOrderIndex,
+	pair: PairIndex,
+	owner: AccountId,
+	side: OrderSide,
 	volume: Balance,
-	ratio: Balance,
+	trigger: Price,
+	limit: Option<Price>,
 	created: BlockNumber
 }
 
-#[derive(Encode, Decode, Default, PartialEq, Eq)]
+/// Member of a linked order group.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum GroupLeg {
+	/// Limit order resting on a book.
+	Resting(OrderRef),
+	/// Conditional order waiting for its trigger.
+	Conditional(PairIndex, ConditionalOrderIndex)
+}
+
+/// Which fills on a leg resolve its order group.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum GroupPolicy {
+	/// Any fill, including a partial one.
+	AnyFill,
+	/// Only a fill that completes the leg.
+	CompleteFill
+}
+
+/// One-cancels-other group: once a leg fills, the others are cancelled.
+#[derive(Encode, Decode, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
-pub struct SellOrderNativeInfo<AccountId, Balance, BlockNumber> {
-	order_id: SellOrderNativeIndex,
-	pair: PairNativeIndex,
-	seller: AccountId,
-	volume: Balance,
-	ratio: Balance,
+pub struct OrderGroupInfo<AccountId, BlockNumber> {
+	group_id: OrderGroupIndex,
+	owner: AccountId,
+	legs: Vec<GroupLeg>,
+	policy: GroupPolicy,
+	created: BlockNumber
+}
+
+/// Expected result of an order that takes liquidity, as returned by `quote`.
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct Quote<Balance> {
+	/// Volume the order would give: base for buys, target for sells.
+	pub filled: Balance,
+	/// Volume the order would receive, after fees.
+	pub received: Balance,
+	/// Volume-weighted fill price.
+	pub average_price: Price,
+	/// Fee withheld from the received volume.
+	pub fee: Balance,
+	/// Volume left unfilled.
+	pub unfilled: Balance
+}
+
+/// Request to list a new pair, backed by a bond until it is approved or rejected.
+#[derive(Encode, Decode, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ListingProposal<AccountId, Balance, BlockNumber> {
+	proposal_id: ListingIndex,
+	proposer: AccountId,
+	base: AssetId,
+	target: AssetId,
+	bond: Balance,
+	created: BlockNumber
+}
+
+/// Sealed order awaiting its reveal.
+#[derive(Encode, Decode, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct CommitmentInfo<AccountId, Balance, BlockNumber, Hash> {
+	commitment_id: CommitmentIndex,
+	owner: AccountId,
+	hash: Hash,
+	deposit: Balance,
 	created: BlockNumber
 }
 
+/// Where end-of-block matching ran out of weight. The next pass starts with this
+/// pair.
+#[derive(Encode, Decode, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MatchCursorInfo {
+	pair: PairIndex
+}
+
+/// Where triggering conditional orders ran out of weight. The next pass starts with
+/// this order.
+#[derive(Encode, Decode, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct TriggerCursorInfo {
+	pair: PairIndex,
+	order_id: ConditionalOrderIndex
+}
 
 #[derive(Encode, Decode, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
@@ -136,14 +413,79 @@ pub struct TradeInfo<AccountId, Balance,  BlockNumber> {
 	seller: AccountId,
 	buyer: AccountId,
 	volume: Balance,
+	ratio: Price,
+	created: BlockNumber
+}
+
+/// Storage layout written by this release, set at genesis and reached by
+/// `on_runtime_upgrade` from any earlier version.
+const STORAGE_VERSION: u32 = 10;
+
+/// Scale of order ratios before they were stored as `Price`.
+const LEGACY_PRICE_SCALE: u128 = 1_000_000_000_000;
+
+/// Order maps of the token and native books, which shared one layout.
+const LEGACY_ORDER_ITEMS: [&[u8]; 4] = [b"BuyOrder", b"SellOrder", b"BuyOrderNative", b"SellOrderNative"];
+
+/// Order layout of the first release, before expiry. `owner` stands in for
+/// `buyer`/`seller`.
+#[derive(Encode, Decode)]
+struct LegacyOrderInfoV0<AccountId, Balance, BlockNumber> {
+	order_id: BuyOrderIndex,
+	pair: PairIndex,
+	owner: AccountId,
+	volume: Balance,
 	ratio: Balance,
 	created: BlockNumber
 }
 
-#[derive(Encode, Decode, Default, PartialEq, Eq)]
-#[cfg_attr(feature = "std", derive(Debug))]
-pub struct TradeNativeInfo<AccountId, Balance,  BlockNumber> {
-	pair: PairNativeIndex,
+/// Order layout after expiry was added.
+#[derive(Encode, Decode)]
+struct LegacyOrderInfoV1<AccountId, Balance, BlockNumber> {
+	order_id: BuyOrderIndex,
+	pair: PairIndex,
+	owner: AccountId,
+	volume: Balance,
+	ratio: Balance,
+	created: BlockNumber,
+	expires_at: Option<BlockNumber>
+}
+
+/// Order layout after iceberg display and reserve volumes were added.
+#[derive(Encode, Decode)]
+struct LegacyOrderInfoV2<AccountId, Balance, BlockNumber> {
+	order_id: BuyOrderIndex,
+	pair: PairIndex,
+	owner: AccountId,
+	volume: Balance,
+	ratio: Balance,
+	display: Balance,
+	reserve: Balance,
+	created: BlockNumber,
+	expires_at: Option<BlockNumber>
+}
+
+/// Order layout after client order ids were added, before orders carried an arrival
+/// sequence number. `Ratio` is a balance scaled by `LEGACY_PRICE_SCALE` until prices
+/// were stored as `Price`.
+#[derive(Encode, Decode)]
+struct LegacyOrderInfo<AccountId, Balance, BlockNumber, Ratio> {
+	order_id: BuyOrderIndex,
+	pair: PairIndex,
+	owner: AccountId,
+	volume: Balance,
+	ratio: Ratio,
+	display: Balance,
+	reserve: Balance,
+	created: BlockNumber,
+	expires_at: Option<BlockNumber>,
+	client_id: Option<ClientOrderId>
+}
+
+/// Trade layout before ratios were stored as `Price`.
+#[derive(Encode, Decode)]
+struct LegacyTradeInfo<AccountId, Balance, BlockNumber> {
+	pair: PairIndex,
 	seller: AccountId,
 	buyer: AccountId,
 	volume: Balance,
@@ -151,86 +493,302 @@ pub struct TradeNativeInfo<AccountId, Balance,  BlockNumber> {
 	created: BlockNumber
 }
 
+/// Conditional order layout before prices were stored as `Price`.
+#[derive(Encode, Decode)]
+struct LegacyConditionalOrderInfo<AccountId, Balance, BlockNumber> {
+	order_id: ConditionalOrderIndex,
+	pair: PairIndex,
+	owner: AccountId,
+	side: OrderSide,
+	volume: Balance,
+	trigger: Balance,
+	limit: Option<Balance>,
+	created: BlockNumber
+}
+
+/// Book an order was kept in before native pairs were merged into `Pair`.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+enum BookKind {
+	Token,
+	Native
+}
+
+/// Order location before native pairs were merged into `Pair`.
+#[derive(Encode, Decode)]
+struct LegacyOrderRef {
+	book: BookKind,
+	side: OrderSide,
+	pair: PairIndex,
+	order_id: BuyOrderIndex
+}
+
+/// Group leg before native pairs were merged into `Pair`.
+#[derive(Encode, Decode)]
+enum LegacyGroupLeg {
+	Resting(LegacyOrderRef),
+	Conditional(BookKind, PairIndex, ConditionalOrderIndex)
+}
+
+/// Order group layout before native pairs were merged into `Pair`.
+#[derive(Encode, Decode)]
+struct LegacyOrderGroupInfo<AccountId, BlockNumber> {
+	group_id: OrderGroupIndex,
+	owner: AccountId,
+	legs: Vec<LegacyGroupLeg>,
+	policy: GroupPolicy,
+	created: BlockNumber
+}
+
+/// Token pair layout before per-pair market parameters.
+#[derive(Encode, Decode)]
+struct LegacyPairInfoV0<AccountId, BlockNumber> {
+	base: TokenIndex,
+	target: TokenIndex,
+	banker: AccountId,
+	active: bool,
+	created: BlockNumber
+}
+
+/// Native pair layout before per-pair market parameters.
+#[derive(Encode, Decode)]
+struct LegacyPairNativeInfoV0<AccountId, BlockNumber> {
+	target: TokenIndex,
+	banker: AccountId,
+	active: bool,
+	created: BlockNumber
+}
+
+/// Token pair layout before pairs held an `AssetId` on each side.
+#[derive(Encode, Decode)]
+struct LegacyPairInfo<AccountId, Balance, BlockNumber> {
+	base: TokenIndex,
+	target: TokenIndex,
+	banker: AccountId,
+	active: bool,
+	created: BlockNumber,
+	tick_size: Price,
+	lot_size: Balance,
+	min_notional: Balance
+}
+
+/// Pair layout before asset metadata was kept on the pair.
+#[derive(Encode, Decode)]
+struct LegacyAssetPairInfo<AccountId, Balance, BlockNumber> {
+	base: AssetId,
+	target: AssetId,
+	banker: AccountId,
+	active: bool,
+	created: BlockNumber,
+	tick_size: Price,
+	lot_size: Balance,
+	min_notional: Balance
+}
+
+/// Native pair layout, trading a token against the native coin.
+#[derive(Encode, Decode)]
+struct LegacyPairNativeInfo<AccountId, Balance, BlockNumber> {
+	target: TokenIndex,
+	banker: AccountId,
+	active: bool,
+	created: BlockNumber,
+	tick_size: Price,
+	lot_size: Balance,
+	min_notional: Balance
+}
+
+/// Commitment layout before native pairs were merged into `Pair`.
+#[derive(Encode, Decode)]
+struct LegacyCommitmentInfo<AccountId, Balance, BlockNumber, Hash> {
+	commitment_id: CommitmentIndex,
+	owner: AccountId,
+	book: BookKind,
+	hash: Hash,
+	deposit: Balance,
+	created: BlockNumber
+}
+
+type LegacyOrderInfoV0Of<T> = LegacyOrderInfoV0<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyOrderInfoV1Of<T> = LegacyOrderInfoV1<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyOrderInfoV2Of<T> = LegacyOrderInfoV2<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyOrderInfoOf<T, Ratio> = LegacyOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber, Ratio>;
+type LegacyTradeInfoOf<T> = LegacyTradeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyConditionalOrderInfoOf<T> = LegacyConditionalOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyPairInfoV0Of<T> = LegacyPairInfoV0<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyPairNativeInfoV0Of<T> = LegacyPairNativeInfoV0<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyPairInfoOf<T> = LegacyPairInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyAssetPairInfoOf<T> = LegacyAssetPairInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyPairNativeInfoOf<T> = LegacyPairNativeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyOrderGroupInfoOf<T> = LegacyOrderGroupInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
+type LegacyCommitmentInfoOf<T> = LegacyCommitmentInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber, <T as system::Trait>::Hash>;
+
 decl_storage! {
 	trait Store for Module<T: Trait> as Exchange {
 
+		pub StorageVersion get(fn storage_version) build(|_| STORAGE_VERSION): u32;
+
 		pub AccountOperation get(fn account_operation): AccountIdOf<T>;
 		pub AccountVault get(fn account_vault): AccountIdOf<T>;
 		pub MinimumVolume get(fn minimum_volume): BalanceOf<T>;
 
-		pub Pair get(fn pair): 
+		pub Pair get(fn pair):
 			map hasher(blake2_128_concat) PairIndex => Option<PairInfoOf<T>>;
-		pub PairCount get(fn pair_count): PairIndex;	
+		pub PairCount get(fn pair_count): PairIndex;
+		/// Pair trading `target` against `base`, by `(base, target)`.
+		pub PairOf get(fn pair_of):
+			map hasher(blake2_128_concat) (AssetId, AssetId) => Option<PairIndex>;
 
-		pub PairNative get(fn pair_native): 
-			map hasher(blake2_128_concat) PairIndex => Option<PairNativeInfoOf<T>>;
-		pub PairNativeCount get(fn pair_native_count): PairIndex;			
+		/// Delisted pairs whose orders are still being refunded, oldest first.
+		pub PairDelisting get(fn pair_delisting): Vec<PairIndex>;
 
-		pub BuyOrder get(fn buy_order): 
+		pub PairMatchingMode get(fn matching_mode):
+			map hasher(blake2_128_concat) PairIndex => MatchingMode;
+
+		pub BuyOrder get(fn buy_order):
 			map hasher(blake2_128_concat) (PairIndex, BuyOrderIndex) => BuyOrderInfoOf<T>;
-		pub BuyOrderList get(fn buy_order_list): 
-			map hasher(blake2_128_concat) PairIndex => Vec<BuyOrderIndex>;
-		pub BuyOrderCount get(fn buy_order_count): 
+		pub BuyOrderCount get(fn buy_order_count):
 			map hasher(blake2_128_concat) PairIndex => BuyOrderIndex;
 
-		pub BuyOrderNative get(fn buy_order_native): 
-			map hasher(blake2_128_concat) (PairNativeIndex, BuyOrderNativeIndex) => BuyOrderNativeInfoOf<T>;
-		pub BuyOrderNativeList get(fn buy_order_native_list): 
-			map hasher(blake2_128_concat) PairNativeIndex => Vec<BuyOrderNativeIndex>;
-		pub BuyOrderNativeCount get(fn buy_order_native_count): 
-			map hasher(blake2_128_concat) PairNativeIndex => BuyOrderNativeIndex;
-
-		pub SellOrder get(fn sell_order): 
+		pub SellOrder get(fn sell_order):
 			map hasher(blake2_128_concat) (PairIndex, SellOrderIndex) => SellOrderInfoOf<T>;
-		pub SellOrderList get(fn sell_order_list): 
-			map hasher(blake2_128_concat) PairIndex => Vec<SellOrderIndex>;
-		pub SellOrderCount get(fn sell_order_count): 
+		pub SellOrderCount get(fn sell_order_count):
 			map hasher(blake2_128_concat) PairIndex => SellOrderIndex;
 
-		pub SellOrderNative get(fn sell_order_native): 
-			map hasher(blake2_128_concat) (PairNativeIndex, SellOrderNativeIndex) => SellOrderNativeInfoOf<T>;
-		pub SellOrderNativeList get(fn sell_order_native_list): 
-			map hasher(blake2_128_concat) PairNativeIndex => Vec<SellOrderNativeIndex>;
-		pub SellOrderNativeCount get(fn sell_order_native_count): 
-			map hasher(blake2_128_concat) PairNativeIndex => SellOrderNativeIndex;
+		/// Open prices on each side of a book, best first.
+		pub PriceLevels get(fn price_levels):
+			map hasher(blake2_128_concat) (OrderSide, PairIndex) => Vec<Price>;
+		/// Orders resting at a price, in arrival order.
+		pub LevelOrders get(fn level_orders):
+			map hasher(blake2_128_concat) (OrderSide, PairIndex, Price) => Vec<BuyOrderIndex>;
+		/// Arrival sequence number of the next order placed on or requeued in a book.
+		pub OrderSequence get(fn order_sequence): u64;
 
-		pub Trades get(fn trades): 
+		pub Trades get(fn trades):
 			map hasher(blake2_128_concat) (PairIndex, TradeIndex) => Option<TradeInfoOf<T>>;
-		pub TradeCount get(fn trade_count): 
+		pub TradeCount get(fn trade_count):
 			map hasher(blake2_128_concat) PairIndex => TradeIndex;
-		
-		pub TradeNatives get(fn trade_natives): 
-			map hasher(blake2_128_concat) (PairNativeIndex, TradeNativeIndex) => Option<TradeNativeInfoOf<T>>;
-		pub TradeNativeCount get(fn trade_native_count): 
-			map hasher(blake2_128_concat) PairNativeIndex => TradeNativeIndex;		
+
+		pub ConditionalOrder get(fn conditional_order):
+			map hasher(blake2_128_concat) (PairIndex, ConditionalOrderIndex) => Option<ConditionalOrderInfoOf<T>>;
+		pub ConditionalOrderList get(fn conditional_order_list):
+			map hasher(blake2_128_concat) PairIndex => Vec<ConditionalOrderIndex>;
+		pub ConditionalOrderCount get(fn conditional_order_count):
+			map hasher(blake2_128_concat) PairIndex => ConditionalOrderIndex;
+
+		pub OrderGroup get(fn order_group):
+			map hasher(blake2_128_concat) OrderGroupIndex => Option<OrderGroupInfoOf<T>>;
+		pub OrderGroupOf get(fn order_group_of):
+			map hasher(blake2_128_concat) GroupLeg => Option<OrderGroupIndex>;
+		pub OrderGroupCount get(fn order_group_count): OrderGroupIndex;
+		/// Bracket stops holding no escrow of their own, with the take-profit order whose
+		/// escrow they share.
+		pub SharedEscrow get(fn shared_escrow):
+			map hasher(blake2_128_concat) (PairIndex, ConditionalOrderIndex) => Option<OrderRef>;
+
+		/// Open orders of each account, across all pairs.
+		pub AccountOrders get(fn account_orders):
+			map hasher(blake2_128_concat) AccountIdOf<T> => Vec<OrderRef>;
+
+		/// Open orders by the id their owner assigned at creation.
+		pub ClientOrders get(fn client_orders):
+			double_map hasher(blake2_128_concat) AccountIdOf<T>, hasher(blake2_128_concat) ClientOrderId => Option<OrderRef>;
+		/// Client order ids each account used most recently, oldest first. They can not
+		/// be reused even once their orders are closed.
+		pub UsedClientOrderIds get(fn used_client_order_ids):
+			map hasher(blake2_128_concat) AccountIdOf<T> => Vec<ClientOrderId>;
+
+		pub MatchCursor get(fn match_cursor): Option<MatchCursorInfo>;
+		pub TriggerCursor get(fn trigger_cursor): Option<TriggerCursorInfo>;
+
+		pub Commitments get(fn commitments):
+			map hasher(blake2_128_concat) CommitmentIndex => Option<CommitmentInfoOf<T>>;
+		pub CommitmentCount get(fn commitment_count): CommitmentIndex;
+		/// Commitments to refund, by the first block after their reveal window.
+		pub CommitmentExpiry get(fn commitment_expiry):
+			map hasher(blake2_128_concat) T::BlockNumber => Vec<CommitmentIndex>;
+
+		/// Pair listings awaiting approval.
+		pub ListingProposals get(fn listing_proposal):
+			map hasher(blake2_128_concat) ListingIndex => Option<ListingProposalOf<T>>;
+		pub ListingCount get(fn listing_count): ListingIndex;
+
+		pub OrderExpiry get(fn order_expiry):
+			map hasher(blake2_128_concat) T::BlockNumber => Vec<OrderRef>;
 	}
 }
 
 decl_event! {
 	pub enum Event<T> where
 		Balance = BalanceOf<T>,
-		//AccountId = <T as system::Trait>::AccountId,
+		AccountId = <T as system::Trait>::AccountId,
 		<T as system::Trait>::BlockNumber,
 	{
 		/// Pair successfully created. \[pair_id, block_number\]
 		PairCreated(PairIndex, BlockNumber),
-		/// Native Pair successfully created. \[pair_id, block_number\]
-		PairNativeCreated(PairIndex, BlockNumber),		
 		/// Pair is paused/unpause trading. \[pair_id, pause\]
 		PairPaused(PairIndex, bool),
-		/// Buy order successfully created. \[order_id, pair_id, ratio, volume\]
-		BuyOrderCreated(BuyOrderIndex, PairIndex),
-		/// Buy native order successfully created. \[order_id, pair_id, ratio, volume\]
-		BuyOrderNativeCreated(BuyOrderIndex, PairIndex),		
-		/// Sell order successfully created. \[order_id, pair_id, ratio, volume\]
-		SellOrderCreated(SellOrderIndex, PairIndex),
-		/// Sell native order successfully created. \[order_id, pair_id, ratio, volume\]
-		SellOrderNativeCreated(SellOrderIndex, PairIndex),		
-		/// Trade successfully created. \[trade_id, pair_id, ratio, volume\]
-		TradeCreated(TradeIndex, PairIndex, Balance, Balance),
+		/// Pair removed after every order on it was refunded. \[pair_id\]
+		PairDelisted(PairIndex),
+		/// Sealed order committed. \[account, commitment_id\]
+		OrderCommitted(AccountId, CommitmentIndex),
+		/// Sealed order revealed and placed, resting as `order_id` unless it filled on
+		/// placement. \[account, commitment_id, order_id\]
+		CommitmentRevealed(AccountId, CommitmentIndex, Option<BuyOrderIndex>),
+		/// Commitment not revealed in time; deposit refunded less the penalty. \[account, commitment_id, penalty, refunded\]
+		CommitmentForfeited(AccountId, CommitmentIndex, Balance, Balance),
+		/// New pair proposed for listing. \[account, proposal_id, base, target\]
+		ListingProposed(AccountId, ListingIndex, AssetId, AssetId),
+		/// Listing proposal approved and its pair created. \[proposal_id, pair_id\]
+		ListingApproved(ListingIndex, PairIndex),
+		/// Listing proposal rejected; the bond is slashed or returned. \[proposal_id, slashed\]
+		ListingRejected(ListingIndex, bool),
+		/// Auction cleared a pair at a single price. \[pair_id, price, target_volume, base_volume\]
+		AuctionCleared(PairIndex, Price, Balance, Balance),
+		/// Matching ran out of block weight and resumes next block. \[pair_id\]
+		MatchingSuspended(PairIndex),
+		/// Market parameters of a pair changed. \[pair_id, tick_size, lot_size, min_notional\]
+		PairMarketParamsSet(PairIndex, Price, Balance, Balance),
+		/// Matching mode of a pair changed. \[pair_id, mode\]
+		PairMatchingModeSet(PairIndex, MatchingMode),
+		/// Buy order successfully created. \[order_id, pair_id, ratio, volume, account, client_id\]
+		BuyOrderCreated(BuyOrderIndex, PairIndex, Price, Balance, AccountId, Option<ClientOrderId>),
+		/// Sell order successfully created. \[order_id, pair_id, ratio, volume, account, client_id\]
+		SellOrderCreated(SellOrderIndex, PairIndex, Price, Balance, AccountId, Option<ClientOrderId>),
 		/// Trade successfully created. \[trade_id, pair_id, ratio, volume\]
-		TradeNativeCreated(TradeIndex, PairIndex, Balance, Balance),	
-				
+		TradeCreated(TradeIndex, PairIndex, Price, Balance),
+		/// Order cancelled and escrow refunded. \[account, pair_id, order_id, side, refunded, client_id\]
+		OrderCancelled(AccountId, PairIndex, BuyOrderIndex, OrderSide, Balance, Option<ClientOrderId>),
+		/// Order expired and escrow refunded. \[account, pair_id, order_id, side, refunded, client_id\]
+		OrderExpired(AccountId, PairIndex, BuyOrderIndex, OrderSide, Balance, Option<ClientOrderId>),
+		/// Incoming order matched against the book on placement. \[account, pair_id, side, given, received, client_id\]
+		OrderMatchedOnPlacement(AccountId, PairIndex, OrderSide, Balance, Balance, Option<ClientOrderId>),
+		/// Immediate order closed and unfilled volume refunded. \[account, pair_id, side, filled, refunded, client_id\]
+		ImmediateOrderClosed(AccountId, PairIndex, OrderSide, Balance, Balance, Option<ClientOrderId>),
+		/// Market order executed. \[account, pair_id, side, given, received, refunded\]
+		MarketOrderExecuted(AccountId, PairIndex, OrderSide, Balance, Balance, Balance),
+		/// Routed swap executed across a path of pairs. \[account, from, to, given, received\]
+		RouteExecuted(AccountId, AssetId, AssetId, Balance, Balance),
+		/// Conditional order placed. \[account, pair_id, order_id\]
+		ConditionalOrderCreated(AccountId, PairIndex, ConditionalOrderIndex),
+		/// Conditional order cancelled and escrow refunded. \[account, pair_id, order_id, refunded\]
+		ConditionalOrderCancelled(AccountId, PairIndex, ConditionalOrderIndex, Balance),
+		/// Conditional order triggered by the last trade price. \[pair_id, order_id, last_price\]
+		ConditionalOrderTriggered(PairIndex, ConditionalOrderIndex, Price),
+		/// Triggered conditional order released to the book. \[pair_id, order_id, resting_order_id\]
+		/// `resting_order_id` is unset for stop-market orders, which execute immediately.
+		ConditionalOrderConverted(PairIndex, ConditionalOrderIndex, Option<BuyOrderIndex>),
+		/// Orders linked into a one-cancels-other group. \[account, group_id\]
+		OrderGroupCreated(AccountId, OrderGroupIndex),
+		/// A leg filled and the rest of its group was cancelled. \[group_id, filled_leg\]
+		OrderGroupResolved(OrderGroupIndex, GroupLeg),
+		/// Group dissolved after its other legs left the book. \[group_id\]
+		OrderGroupDissolved(OrderGroupIndex),
+		/// Iceberg slice refilled from reserve. \[pair_id, side, order_id, visible\]
+		IcebergReplenished(PairIndex, OrderSide, BuyOrderIndex, Balance),
+		/// Resting order (partially) filled. \[account, pair_id, order_id, side, filled, client_id\]
+		OrderFilled(AccountId, PairIndex, BuyOrderIndex, OrderSide, Balance, Option<ClientOrderId>),
+		/// Order volume and/or ratio amended. \[account, pair_id, order_id, side, volume, ratio\]
+		OrderAmended(AccountId, PairIndex, BuyOrderIndex, OrderSide, Balance, Price),
+
 	}
 }
 
@@ -243,7 +801,76 @@ decl_error! {
 
 		NotTokenOwner,
 		InsufficientAmount,
-		InsufficientApproval,		
+		InsufficientApproval,
+		/// Order does not exist
+		OrderNotFound,
+		/// Order belongs to another account
+		NotOrderOwner,
+		/// Order ratio can not be zero
+		ZeroRatio,
+		/// Post-only order, or an order that can not match on placement on a continuous
+		/// pair, would take liquidity from the book
+		WouldCrossBook,
+		/// Fill-or-kill order can not be filled completely
+		CannotFillOrKill,
+		/// Pair does not exist
+		PairNotFound,
+		/// Not enough resting volume on the book to fill the order
+		InsufficientLiquidity,
+		/// Average fill price is worse than the caller's limit
+		SlippageExceeded,
+		/// Conditional order does not exist
+		ConditionalOrderNotFound,
+		/// An order group needs at least two legs
+		TooFewLegs,
+		/// Order is already part of an order group
+		OrderAlreadyLinked,
+		/// Iceberg display volume must be below the total volume
+		InvalidDisplayVolume,
+		/// Client order id is used by an open order of this account, or was used recently
+		DuplicateClientOrderId,
+		/// Amendment leaves volume and ratio unchanged
+		NothingToAmend,
+		/// Order volume can not be zero
+		ZeroVolume,
+		/// Expiry block must be in the future
+		InvalidExpiry,
+		/// Order ratio is not a multiple of the pair's tick size
+		InvalidTickSize,
+		/// Order volume in target is not a multiple of the pair's lot size
+		InvalidLotSize,
+		/// Order value is below the pair's minimum notional
+		BelowMinimumNotional,
+		/// Commitment does not exist
+		CommitmentNotFound,
+		/// Reveal window of the commitment has not opened yet
+		RevealTooEarly,
+		/// Reveal window of the commitment has closed
+		RevealWindowClosed,
+		/// Revealed order does not match the commitment hash
+		RevealMismatch,
+		/// Route is empty, too long or its pairs do not connect
+		InvalidRoute,
+		/// Pair is being delisted
+		PairDelisting,
+		/// A pair with the same base and target already exists
+		PairExists,
+		/// Base and target of a pair must differ
+		IdenticalAssets,
+		/// Pair needs a banker, passed explicitly or taken from a signed origin
+		NoBanker,
+		/// Listing proposal does not exist
+		ListingNotFound,
+		/// Asset does not exist
+		UnknownAsset,
+		/// Asset is disabled and can not be listed
+		AssetDisabled,
+		/// An order group can not hold both sides of the same pair
+		OpposingLegs,
+		/// Orders that trade immediately are not accepted on pairs matched by auction
+		ImmediateOrderOnAuction,
+		/// An order group can link at most `MaxGroupLegs` orders
+		TooManyLegs,
 	}
 }
 
@@ -251,11 +878,11 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
-		type Error = Error<T>;		
-		
+		type Error = Error<T>;
+
 		#[weight = 10_000]
 		fn exchange_accounts(origin, account_type:u32, account_id:AccountIdOf<T>) {
-			let _creator = ensure_root(origin)?;
+			T::AccountsOrigin::ensure_origin(origin)?;
 			if account_type == 1 {
 				<AccountOperation<T>>::put(account_id)
 			} else if account_type == 2 {
@@ -265,614 +892,2716 @@ decl_module! {
 
 		#[weight = 10_000]
 		fn exchange_fees(origin, fee_type:u32, fee:BalanceOf<T>) {
-			let _creator = ensure_root(origin)?;
+			T::ParamsOrigin::ensure_origin(origin)?;
 			if fee_type == 1 {
 				<MinimumVolume<T>>::put(fee)
 			} else if fee_type == 2 {
 				//<MinimumVolumet<T>>::put(fee)
 			} else if fee_type == 3 {
 			}
-		}		
+		}
 
+		/// Open a pair trading `target` against `base`. Either side may be the native
+		/// coin or a token.
+		///
+		/// The pair is managed by `banker`, or by the caller when it is left unset and
+		/// the origin is signed.
 		#[weight = 10_000]
 		fn exchange_pair_create(
 			origin,
-			base: u32,
-			target: u32
-			) {		
-			let banker = ensure_signed(origin.clone())?;
-			ensure_root(origin)?;
-			let created = <system::Module<T>>::block_number();
-			let active: bool = true;
+			base: AssetId,
+			target: AssetId,
+			banker: Option<AccountIdOf<T>>
+			) {
+			T::CreatePairOrigin::ensure_origin(origin.clone())?;
+			let banker = banker.or_else(|| ensure_signed(origin).ok()).ok_or(Error::<T>::NoBanker)?;
+			Self::create_pair(base, target, banker)?;
+		}
 
-			let index = PairCount::get();
-			PairCount::put(index + 1);
+		/// Choose whether `pair` matches orders on placement or once per block.
+		#[weight = 10_000]
+		fn exchange_pair_matching_mode(
+			origin,
+			pair: PairIndex,
+			mode: MatchingMode
+			) {
+			T::ParamsOrigin::ensure_origin(origin)?;
+			ensure!(Self::pair_exists(pair), Error::<T>::PairNotFound);
 
-			<Pair<T>>::insert(index, PairInfo {
-				base,
-				target,
-				banker,
-				active,
-				created
-			});
+			// Anything that crossed while the pair was batched is cleared by the
+			// budgeted matching in `on_finalize`.
+			<PairMatchingMode>::insert(pair, mode);
 
-			Self::deposit_event(RawEvent::PairCreated(index, created));
-		}	
+			Self::deposit_event(RawEvent::PairMatchingModeSet(pair, mode));
+		}
 
+		/// Stop trading on `pair`. Resting orders stay on the book and can still be
+		/// cancelled, but nothing is placed or matched until the pair is resumed.
 		#[weight = 10_000]
-		fn exchange_pair_native_create(
+		fn exchange_pair_pause(
 			origin,
-			target: u32
+			pair: PairIndex
 			) {
-			let banker = ensure_signed(origin.clone())?;
-			ensure_root(origin)?;
-			let created = <system::Module<T>>::block_number();
-			let active: bool = true;
+			T::PauseOrigin::ensure_origin(origin)?;
+			Self::set_pair_active(pair, false)?;
+			Self::deposit_event(RawEvent::PairPaused(pair, true));
+		}
 
-			let index = PairNativeCount::get();
-			PairNativeCount::put(index + 1);
+		#[weight = 10_000]
+		fn exchange_pair_resume(
+			origin,
+			pair: PairIndex
+			) {
+			T::PauseOrigin::ensure_origin(origin)?;
+			ensure!(!<PairDelisting>::get().contains(&pair), Error::<T>::PairDelisting);
+			Self::set_pair_active(pair, true)?;
+			Self::deposit_event(RawEvent::PairPaused(pair, false));
+		}
 
-			<PairNative<T>>::insert(index, PairNativeInfo {
-				target,
-				banker,
-				active,
-				created
-			});
+		/// Pause `pair` for good and wind it down. Its resting and conditional orders are
+		/// refunded over the following blocks, at most `MaxDelistRefundsPerBlock` per
+		/// block, after which the pair is removed.
+		#[weight = 10_000]
+		fn exchange_pair_delist(
+			origin,
+			pair: PairIndex
+			) {
+			T::PauseOrigin::ensure_origin(origin)?;
+			ensure!(!<PairDelisting>::get().contains(&pair), Error::<T>::PairDelisting);
+			Self::set_pair_active(pair, false)?;
+			<PairDelisting>::append(pair);
+			Self::deposit_event(RawEvent::PairPaused(pair, true));
+		}
 
-			Self::deposit_event(RawEvent::PairNativeCreated(index, created));
-		}		
-	
+		/// Set the tick size, lot size and minimum notional that orders on `pair` must
+		/// respect. Resting orders are left as they are.
 		#[weight = 10_000]
+		fn exchange_pair_market_params(
+			origin,
+			pair: PairIndex,
+			tick_size: Price,
+			lot_size: BalanceOf<T>,
+			min_notional: BalanceOf<T>
+			) {
+			T::ParamsOrigin::ensure_origin(origin)?;
+			<Pair<T>>::try_mutate(pair, |info| -> DispatchResult {
+				let info = info.as_mut().ok_or(Error::<T>::PairNotFound)?;
+				info.tick_size = tick_size;
+				info.lot_size = lot_size;
+				info.min_notional = min_notional;
+				Ok(())
+			})?;
+
+			Self::deposit_event(RawEvent::PairMarketParamsSet(pair, tick_size, lot_size, min_notional));
+		}
+
+		#[weight = <Module<T>>::placement_weight().saturating_add(<Module<T>>::taker_weight())]
 		fn exchange_order_create_buy(
 			origin,
 			pair: PairIndex,
 			volume: BalanceOf<T>,
-			ratio: BalanceOf<T>) {
-			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			let base = <Pair<T>>::get(pair).unwrap().base;		
-			let volume = volume;
-			let ratio = ratio;
+			ratio: Price,
+			expires_at: Option<T::BlockNumber>,
+			time_in_force: TimeInForce,
+			client_id: Option<ClientOrderId>) {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_active(pair)?;
+			let created = <system::Module<T>>::block_number();
+			Self::ensure_expiry(created, expires_at)?;
+			Self::ensure_client_id(&caller, client_id)?;
+			if time_in_force.is_immediate() {
+				Self::ensure_not_auction(pair)?;
+			}
+			Self::ensure_time_in_force(OrderSide::Buy, pair, volume, ratio, time_in_force)?;
+			Self::ensure_market_params(OrderSide::Buy, pair, volume, ratio)?;
+			Self::lock_escrow(OrderSide::Buy, pair, caller.clone(), volume)?;
+			Self::note_client_id(&caller, client_id);
+
+			if time_in_force.is_immediate() {
+				Self::execute_immediate(OrderSide::Buy, pair, caller, volume, ratio, client_id);
+				return Ok(());
+			}
 
-			let exchange = Self::account_operation();
+			Self::place_order(OrderSide::Buy, pair, caller, volume, ratio, created, expires_at, client_id);
+		}
 
-			let base_balance = <Token::Module<T>>::get_balance(base, caller.clone());				
-			ensure!(base_balance >= volume, Error::<T>::InsufficientAmount);
+		#[weight = <Module<T>>::placement_weight().saturating_add(<Module<T>>::taker_weight())]
+		fn exchange_order_create_sell(
+			origin,
+			pair: PairIndex,
+			volume: BalanceOf<T>,
+			ratio: Price,
+			expires_at: Option<T::BlockNumber>,
+			time_in_force: TimeInForce,
+			client_id: Option<ClientOrderId>) {
 
-			let _volume = volume;
-			let _ratio = ratio;	
+			let caller = ensure_signed(origin)?;
+			Self::ensure_active(pair)?;
+			let created = <system::Module<T>>::block_number();
+			Self::ensure_expiry(created, expires_at)?;
+			Self::ensure_client_id(&caller, client_id)?;
+			if time_in_force.is_immediate() {
+				Self::ensure_not_auction(pair)?;
+			}
+			Self::ensure_time_in_force(OrderSide::Sell, pair, volume, ratio, time_in_force)?;
+			Self::ensure_market_params(OrderSide::Sell, pair, volume, ratio)?;
+			Self::lock_escrow(OrderSide::Sell, pair, caller.clone(), volume)?;
+			Self::note_client_id(&caller, client_id);
+
+			if time_in_force.is_immediate() {
+				Self::execute_immediate(OrderSide::Sell, pair, caller, volume, ratio, client_id);
+				return Ok(());
+			}
 
-			<Token::Module<T>>::transfer_(base, caller.clone(), exchange.clone(), _volume.clone());					
+			Self::place_order(OrderSide::Sell, pair, caller, volume, ratio, created, expires_at, client_id);
+		}
 
-			let index = <BuyOrderCount>::get(pair);			
+		/// Trade `volume` right away against the opposite side of the book.
+		///
+		/// `volume` is in base for buys and in target for sells. `worst_ratio` is the
+		/// worst volume-weighted price accepted; with `allow_partial` set the order fills
+		/// as far as it can within that price and refunds the rest instead of failing.
+		#[weight = <Module<T>>::taker_weight().saturating_mul(2)
+			.saturating_add(T::DbWeight::get().reads_writes(4, 2))]
+		fn exchange_order_market(
+			origin,
+			pair: PairIndex,
+			side: OrderSide,
+			volume: BalanceOf<T>,
+			worst_ratio: Price,
+			allow_partial: bool) {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_active(pair)?;
+			Self::ensure_not_auction(pair)?;
+			Self::execute_market(side, pair, caller, volume, worst_ratio, allow_partial)?;
+		}
 
-			<BuyOrder<T>>::insert((pair, index), BuyOrderInfo {
-				order_id: index,
-				pair,
-				buyer: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-			let mut buy_order_list = <BuyOrderList>::get(pair);
-			buy_order_list.insert(buy_order_list.len(), index);
-			<BuyOrderList>::insert(pair, buy_order_list);
-			<BuyOrderCount>::insert(pair, index + 1);
-			//Self::deposit_event(RawEvent::BuyOrderCreated(index, pair, _ratio, _volume));
-		}	
+		/// Swap `volume` of `from` through the pairs of `path`, in order, taking liquidity
+		/// from each book at the best available prices.
+		///
+		/// Each leg trades whatever the previous one received, after fees; volume a leg
+		/// can not fill is returned. The whole swap is rolled back if any leg fails or
+		/// the final output is below `min_output`.
+		#[weight = <Module<T>>::taker_weight().saturating_add(T::DbWeight::get().reads_writes(4, 2))
+			.saturating_mul(path.len() as Weight)]
+		fn exchange_order_route(
+			origin,
+			from: AssetId,
+			path: Vec<PairIndex>,
+			volume: BalanceOf<T>,
+			min_output: BalanceOf<T>) {
+			let caller = ensure_signed(origin)?;
+			ensure!(!path.is_empty() && path.len() <= T::MaxRouteLength::get() as usize, Error::<T>::InvalidRoute);
+			ensure!(!volume.is_zero(), Error::<T>::ZeroVolume);
+			for pair in path.iter() {
+				Self::ensure_not_auction(*pair)?;
+			}
+			Self::execute_route(caller, from, path, volume, min_output)?;
+		}
 
-		#[weight = 10_000]
-		fn exchange_order_native_create_buy(
+		/// Place an iceberg limit order showing at most `display_volume` of `volume`.
+		///
+		/// The rest is held in reserve. Whenever the visible slice fills, it is refilled
+		/// from the reserve and moves to the back of the queue.
+		#[weight = <Module<T>>::placement_weight().saturating_add(T::DbWeight::get().writes(1))]
+		fn exchange_order_create_iceberg(
 			origin,
 			pair: PairIndex,
+			side: OrderSide,
 			volume: BalanceOf<T>,
-			ratio: BalanceOf<T>) {
-			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			
-			let volume = volume;
-			let ratio = ratio;
-
-			let exchange = Self::account_operation();
-			
-
-			let _volume = volume;
-			let _ratio = ratio;	
-
-			Self::transfer_coin(caller.clone(), exchange.clone(), _volume.clone());					
-
-			let index = <BuyOrderNativeCount>::get(pair);			
-
-			<BuyOrderNative<T>>::insert((pair, index), BuyOrderNativeInfo {
-				order_id: index,
-				pair,
-				buyer: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
+			display_volume: BalanceOf<T>,
+			ratio: Price,
+			expires_at: Option<T::BlockNumber>) {
+			let caller = ensure_signed(origin)?;
+			let created = <system::Module<T>>::block_number();
+			Self::ensure_active(pair)?;
+			ensure!(!ratio.is_zero(), Error::<T>::ZeroRatio);
+			ensure!(!display_volume.is_zero(), Error::<T>::ZeroVolume);
+			ensure!(display_volume < volume, Error::<T>::InvalidDisplayVolume);
+			Self::ensure_market_params(side, pair, volume, ratio)?;
+			Self::ensure_expiry(created, expires_at)?;
+			Self::lock_escrow(side, pair, caller.clone(), volume)?;
+
+			// On continuous pairs the whole volume, reserve included, matches on placement.
+			if let Some(order_id) = Self::place_order(side, pair, caller, volume, ratio, created, expires_at, None) {
+				let order = OrderRef { side, pair, order_id };
+				let rest = Self::order_terms(&order).map_or_else(Zero::zero, |(_, rest, _)| rest);
+				let visible = rest.min(display_volume);
+				Self::set_order_reserve(&order, visible, display_volume, rest - visible);
+			}
+		}
 
-			let mut buy_order_list = <BuyOrderNativeList>::get(pair);
-			buy_order_list.insert(buy_order_list.len(), index);			
-			<BuyOrderNativeList>::insert(pair, buy_order_list);
-			<BuyOrderNativeCount>::insert(pair, index + 1);
-			//Self::deposit_event(RawEvent::BuyOrderNativeCreated(index, pair, _ratio, _volume));
-		}			
-	
+		/// Place a stop order that stays off the book until the pair's last trade price
+		/// reaches `trigger_ratio` (at or above it for buys, at or below it for sells).
+		///
+		/// Once triggered it becomes a limit order at `limit_ratio`, or a market order if
+		/// `limit_ratio` is unset. The order's volume is escrowed at placement.
 		#[weight = 10_000]
-		fn exchange_order_create_sell(
+		fn exchange_conditional_create(
 			origin,
 			pair: PairIndex,
+			side: OrderSide,
 			volume: BalanceOf<T>,
-			ratio: BalanceOf<T>) {
-
-			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			let target = <Pair<T>>::get(pair).unwrap().target;	
-			let volume = volume;
-			let ratio = ratio;					
-			let exchange = Self::account_operation();
-			let target_balance = <Token::Module<T>>::get_balance(target, caller.clone());			
-
-			ensure!(target_balance >= volume, Error::<T>::InsufficientAmount);
-
-			let _volume = volume;
-			let _ratio = ratio;	
-			
-			<Token::Module<T>>::transfer_(target, caller.clone(), exchange.clone(), _volume);		
-
-			let index = <SellOrderCount>::get(pair);		
-			<SellOrder<T>>::insert((pair, index), SellOrderInfo {
-				order_id: index,
-				pair,
-				seller: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-			let mut sell_order_list = <SellOrderList>::get(pair);
-			sell_order_list.insert(sell_order_list.len(), index);			
-			<SellOrderList>::insert(pair, sell_order_list);
-			<SellOrderCount>::insert(pair, index + 1);	
-			//Self::deposit_event(RawEvent::SellOrderCreated(index, pair, _ratio, _volume));
-		}	
-		
+			trigger_ratio: Price,
+			limit_ratio: Option<Price>) {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_active(pair)?;
+			ensure!(!volume.is_zero(), Error::<T>::ZeroVolume);
+			ensure!(!trigger_ratio.is_zero(), Error::<T>::ZeroRatio);
+			ensure!(limit_ratio.map_or(true, |r| !r.is_zero()), Error::<T>::ZeroRatio);
+			if limit_ratio.is_none() {
+				Self::ensure_not_auction(pair)?;
+			}
+			Self::ensure_market_params(side, pair, volume, limit_ratio.unwrap_or(trigger_ratio))?;
+			Self::ensure_tick(pair, trigger_ratio)?;
+			Self::lock_escrow(side, pair, caller.clone(), volume)?;
+
+			let created = <system::Module<T>>::block_number();
+			let index = Self::insert_conditional_order(
+				pair, caller.clone(), side, volume, trigger_ratio, limit_ratio, created);
+
+			Self::deposit_event(RawEvent::ConditionalOrderCreated(caller, pair, index));
+		}
+
 		#[weight = 10_000]
-		fn exchange_order_native_create_sell(
+		fn exchange_conditional_cancel(
 			origin,
 			pair: PairIndex,
-			volume: BalanceOf<T>,
-			ratio: BalanceOf<T>) {
-
-			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			let target = <PairNative<T>>::get(pair).unwrap().target;	
-			let volume = volume;
-			let ratio = ratio;					
-			let exchange = Self::account_operation();
-			let target_balance = <Token::Module<T>>::get_balance(target, caller.clone());			
-
-			ensure!(target_balance >= volume, Error::<T>::InsufficientAmount);
-
-			let _volume = volume;
-			let _ratio = ratio;	
-			
-			<Token::Module<T>>::transfer_(target, caller.clone(), exchange.clone(), _volume);		
-
-			let index = <SellOrderNativeCount>::get(pair);		
-			<SellOrderNative<T>>::insert((pair, index), SellOrderNativeInfo {
-				order_id: index,
-				pair,
-				seller: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-			let mut sell_order_list = <SellOrderNativeList>::get(pair);
-			sell_order_list.insert(sell_order_list.len(), index);			
-			<SellOrderNativeList>::insert(pair, sell_order_list);			
-			<SellOrderNativeCount>::insert(pair, index + 1);	
-			//Self::deposit_event(RawEvent::SellOrderNativeCreated(index, pair, _ratio, _volume));
-		}			
-		
-							
-		fn on_finalize(now: T::BlockNumber) {
-			
-			let _now = now;
-			Self::match_native_orders();
-			Self::match_orders();
-
+			order_id: ConditionalOrderIndex) {
+			let caller = ensure_signed(origin)?;
+			let order = <ConditionalOrder<T>>::get((pair, order_id))
+				.ok_or(Error::<T>::ConditionalOrderNotFound)?;
+			ensure!(order.owner == caller, Error::<T>::NotOrderOwner);
+
+			if let Some((_, refunded)) = Self::cancel_conditional_order(pair, order_id) {
+				Self::deposit_event(RawEvent::ConditionalOrderCancelled(caller, pair, order_id, refunded));
+			}
 		}
 
-	}
-}
+		/// Link resting and/or conditional orders so that a fill on one cancels the
+		/// others and refunds their escrow.
+		///
+		/// With `GroupPolicy::AnyFill` the first partial fill resolves the group; with
+		/// `GroupPolicy::CompleteFill` only a complete fill does. A triggered conditional
+		/// leg always resolves it. At most `MaxGroupLegs` orders can be linked.
+		#[weight = T::DbWeight::get().reads_writes(4, 1).saturating_mul(legs.len() as Weight)
+			.saturating_add(T::DbWeight::get().reads_writes(1, 2))]
+		fn exchange_order_link(
+			origin,
+			legs: Vec<GroupLeg>,
+			policy: GroupPolicy) {
+			let caller = ensure_signed(origin)?;
+			Self::link_orders(caller, legs, policy)?;
+		}
 
-impl<T: Trait> Module<T> {
+		/// Place a bracket to exit a position: a take-profit limit order at
+		/// `take_profit_ratio` and a stop at `stop_ratio` (stop-limit at
+		/// `stop_limit_ratio` if given), linked one-cancels-other.
+		///
+		/// `volume` is escrowed once and held by the take-profit. A triggered stop takes
+		/// over whatever the take-profit has left, and refunding the take-profit cancels
+		/// the stop. On continuous pairs a take-profit that would cross the book is
+		/// refused.
+		#[weight = <Module<T>>::placement_weight().saturating_add(T::DbWeight::get().reads_writes(6, 8))]
+		fn exchange_order_bracket(
+			origin,
+			pair: PairIndex,
+			side: OrderSide,
+			volume: BalanceOf<T>,
+			take_profit_ratio: Price,
+			stop_ratio: Price,
+			stop_limit_ratio: Option<Price>,
+			policy: GroupPolicy) {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_active(pair)?;
+			ensure!(!volume.is_zero(), Error::<T>::ZeroVolume);
+			ensure!(!take_profit_ratio.is_zero() && !stop_ratio.is_zero(), Error::<T>::ZeroRatio);
+			ensure!(stop_limit_ratio.map_or(true, |r| !r.is_zero()), Error::<T>::ZeroRatio);
+			if stop_limit_ratio.is_none() {
+				Self::ensure_not_auction(pair)?;
+			}
+			Self::ensure_market_params(side, pair, volume, take_profit_ratio)?;
+			Self::ensure_market_params(side, pair, volume, stop_limit_ratio.unwrap_or(stop_ratio))?;
+			Self::ensure_tick(pair, stop_ratio)?;
+			Self::ensure_no_cross(side, pair, volume, take_profit_ratio)?;
+			Self::lock_escrow(side, pair, caller.clone(), volume)?;
 
-	fn transfer_coin( 
-		from: AccountIdOf<T>, 
-		to: AccountIdOf<T>, 
-		value:BalanceOf<T>) -> () {
+			let created = <system::Module<T>>::block_number();
+			let take_profit = Self::insert_order(
+				side, pair, caller.clone(), volume, take_profit_ratio, created, None, None);
+			let stop = Self::insert_conditional_order(
+				pair, caller.clone(), side, volume, stop_ratio, stop_limit_ratio, created);
+			<SharedEscrow>::insert((pair, stop), OrderRef { side, pair, order_id: take_profit });
+
+			Self::link_orders(caller, vec![
+				GroupLeg::Resting(OrderRef { side, pair, order_id: take_profit }),
+				GroupLeg::Conditional(pair, stop),
+			], policy)?;
+		}
 
-		let _lol = <T as pallet_token::Trait>::Currency::transfer(&from, &to, value, AllowDeath);//.map_err(|_| DispatchError::Other("Can't make transfer"))?;			
-	}	
+		/// Cancel an open order by the client order id it was created with.
+		#[weight = 10_000]
+		fn exchange_order_cancel_by_client_id(
+			origin,
+			client_id: ClientOrderId) {
+			let caller = ensure_signed(origin)?;
+			let order = <ClientOrders<T>>::get(&caller, client_id).ok_or(Error::<T>::OrderNotFound)?;
 
+			if let Some((_, refunded, client_id)) = Self::refund_order(&order) {
+				Self::deposit_event(
+					RawEvent::OrderCancelled(caller, order.pair, order.order_id, order.side, refunded, client_id));
+			}
+		}
 
-	fn swap(pair: u128, 
-		seller: AccountIdOf<T>, 
-		buyer: AccountIdOf<T>,
-		seller_volume: BalanceOf<T>, 
-		buyer_volume: BalanceOf<T>, 
-		ratio:BalanceOf<T>) -> () {
-		
-		let exchange = Self::account_operation();
-		let vault = Self::account_vault();
-		let base = <Pair<T>>::get(pair).unwrap().base;
-		let target = <Pair<T>>::get(pair).unwrap().target;
-		let p999 = sp_runtime::Permill::from_parts(999000);
+		#[weight = 10_000]
+		fn exchange_order_cancel_buy(
+			origin,
+			pair: PairIndex,
+			order_id: BuyOrderIndex) {
+			let caller = ensure_signed(origin)?;
+			ensure!(<BuyOrder<T>>::contains_key((pair, order_id)), Error::<T>::OrderNotFound);
+			ensure!(<BuyOrder<T>>::get((pair, order_id)).buyer == caller, Error::<T>::NotOrderOwner);
+
+			let order = OrderRef { side: OrderSide::Buy, pair, order_id };
+			if let Some((_, refunded, client_id)) = Self::refund_order(&order) {
+				Self::deposit_event(RawEvent::OrderCancelled(caller, pair, order_id, OrderSide::Buy, refunded, client_id));
+			}
+		}
 
-		let base_after_fee = p999 * seller_volume;		
-		<Token::Module<T>>::transfer_(base, exchange.clone(), seller.clone(), base_after_fee);
-		<Token::Module<T>>::transfer_(base, exchange.clone(), vault.clone(), seller_volume - base_after_fee);
-		let target_after_fee = p999 * buyer_volume;
-		<Token::Module<T>>::transfer_(target, exchange.clone(), buyer.clone(), target_after_fee);
-		<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), buyer_volume - target_after_fee);
+		#[weight = 10_000]
+		fn exchange_order_cancel_sell(
+			origin,
+			pair: PairIndex,
+			order_id: SellOrderIndex) {
+			let caller = ensure_signed(origin)?;
+			ensure!(<SellOrder<T>>::contains_key((pair, order_id)), Error::<T>::OrderNotFound);
+			ensure!(<SellOrder<T>>::get((pair, order_id)).seller == caller, Error::<T>::NotOrderOwner);
+
+			let order = OrderRef { side: OrderSide::Sell, pair, order_id };
+			if let Some((_, refunded, client_id)) = Self::refund_order(&order) {
+				Self::deposit_event(RawEvent::OrderCancelled(caller, pair, order_id, OrderSide::Sell, refunded, client_id));
+			}
+		}
 
-		let created = <system::Module<T>>::block_number();	
-		let index = <TradeCount>::get(pair);	
+		/// Replace the volume and/or ratio of a resting order, keeping its id.
+		///
+		/// The escrow difference is topped up from or refunded to the owner. A volume
+		/// decrease keeps the order's queue position; a ratio change or a volume increase
+		/// moves it to the back of the queue.
+		#[weight = <Module<T>>::taker_weight().saturating_add(T::DbWeight::get().reads_writes(8, 8))]
+		fn exchange_order_amend(
+			origin,
+			pair: PairIndex,
+			side: OrderSide,
+			order_id: BuyOrderIndex,
+			volume: Option<BalanceOf<T>>,
+			ratio: Option<Price>) {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_active(pair)?;
+			let asset = Self::escrow_asset(side, pair).ok_or(Error::<T>::PairNotFound)?;
+
+			let (new_volume, new_ratio) = match side {
+				OrderSide::Buy => {
+					ensure!(<BuyOrder<T>>::contains_key((pair, order_id)), Error::<T>::OrderNotFound);
+					let mut order = <BuyOrder<T>>::get((pair, order_id));
+					ensure!(order.buyer == caller, Error::<T>::NotOrderOwner);
+
+					let new_volume = volume.unwrap_or(order.volume);
+					let new_ratio = ratio.unwrap_or(order.ratio);
+					Self::ensure_amendment(order.volume, order.ratio, new_volume, new_ratio)?;
+					Self::ensure_market_params(OrderSide::Buy, pair, new_volume, new_ratio)?;
+					Self::ensure_no_cross(OrderSide::Buy, pair, new_volume, new_ratio)?;
+					Self::settle_escrow(asset, caller.clone(), order.volume, new_volume)?;
+
+					let requeue = new_ratio != order.ratio || new_volume > order.volume;
+					let old_ratio = order.ratio;
+					order.volume = new_volume;
+					order.ratio = new_ratio;
+					<BuyOrder<T>>::insert((pair, order_id), order);
+					if requeue {
+						let order = OrderRef { side: OrderSide::Buy, pair, order_id };
+						Self::requeue(&order, old_ratio, new_ratio);
+					}
+					(new_volume, new_ratio)
+				},
+				OrderSide::Sell => {
+					ensure!(<SellOrder<T>>::contains_key((pair, order_id)), Error::<T>::OrderNotFound);
+					let mut order = <SellOrder<T>>::get((pair, order_id));
+					ensure!(order.seller == caller, Error::<T>::NotOrderOwner);
+
+					let new_volume = volume.unwrap_or(order.volume);
+					let new_ratio = ratio.unwrap_or(order.ratio);
+					Self::ensure_amendment(order.volume, order.ratio, new_volume, new_ratio)?;
+					Self::ensure_market_params(OrderSide::Sell, pair, new_volume, new_ratio)?;
+					Self::ensure_no_cross(OrderSide::Sell, pair, new_volume, new_ratio)?;
+					Self::settle_escrow(asset, caller.clone(), order.volume, new_volume)?;
+
+					let requeue = new_ratio != order.ratio || new_volume > order.volume;
+					let old_ratio = order.ratio;
+					order.volume = new_volume;
+					order.ratio = new_ratio;
+					<SellOrder<T>>::insert((pair, order_id), order);
+					if requeue {
+						let order = OrderRef { side: OrderSide::Sell, pair, order_id };
+						Self::requeue(&order, old_ratio, new_ratio);
+					}
+					(new_volume, new_ratio)
+				},
+			};
+
+			// On continuous pairs an amendment that would cross was refused above;
+			// elsewhere a requeued order that now crosses is matched by the budgeted
+			// matching in `on_finalize`.
+			Self::deposit_event(RawEvent::OrderAmended(caller, pair, order_id, side, new_volume, new_ratio));
+		}
 
-		<Trades<T>>::insert((pair, index), TradeInfo {
-			pair,
-			seller,
-			buyer,
-			volume: buyer_volume,
-			ratio,
+		/// Propose a pair trading `target` against `base`, reserving `ListingBond` until
+		/// the proposal is decided. The proposer becomes the pair's banker.
+		#[weight = 10_000]
+		fn exchange_pair_propose(
+			origin,
+			base: AssetId,
+			target: AssetId) {
+			let caller = ensure_signed(origin)?;
+			ensure!(base != target, Error::<T>::IdenticalAssets);
+			ensure!(!<PairOf>::contains_key((base, target)), Error::<T>::PairExists);
+			Self::listable_metadata(base)?;
+			Self::listable_metadata(target)?;
+
+			let bond = T::ListingBond::get();
+			<T as Trait>::Currency::reserve(&caller, bond).map_err(|_| Error::<T>::InsufficientAmount)?;
+
+			let index = <ListingCount>::get();
+			<ListingCount>::put(index + 1);
+			<ListingProposals<T>>::insert(index, ListingProposal {
+				proposal_id: index,
+				proposer: caller.clone(),
+				base,
+				target,
+				bond,
+				created: <system::Module<T>>::block_number()
+			});
+
+			Self::deposit_event(RawEvent::ListingProposed(caller, index, base, target));
+		}
+
+		/// Create the pair of a listing proposal and return the proposer's bond.
+		#[weight = 10_000]
+		fn exchange_pair_approve(
+			origin,
+			proposal_id: ListingIndex) {
+			T::ListingApproveOrigin::ensure_origin(origin)?;
+			let proposal = <ListingProposals<T>>::get(proposal_id).ok_or(Error::<T>::ListingNotFound)?;
+
+			let pair = Self::create_pair(proposal.base, proposal.target, proposal.proposer.clone())?;
+			<ListingProposals<T>>::remove(proposal_id);
+			<T as Trait>::Currency::unreserve(&proposal.proposer, proposal.bond);
+
+			Self::deposit_event(RawEvent::ListingApproved(proposal_id, pair));
+		}
+
+		/// Drop a listing proposal. With `slash` set the bond goes to `AccountVault`,
+		/// otherwise it is returned to the proposer.
+		#[weight = 10_000]
+		fn exchange_pair_reject(
+			origin,
+			proposal_id: ListingIndex,
+			slash: bool) {
+			T::ListingApproveOrigin::ensure_origin(origin)?;
+			let proposal = <ListingProposals<T>>::take(proposal_id).ok_or(Error::<T>::ListingNotFound)?;
+
+			if slash {
+				let _ = <T as Trait>::Currency::repatriate_reserved(
+					&proposal.proposer, &Self::account_vault(), proposal.bond, BalanceStatus::Free);
+			} else {
+				<T as Trait>::Currency::unreserve(&proposal.proposer, proposal.bond);
+			}
+
+			Self::deposit_event(RawEvent::ListingRejected(proposal_id, slash));
+		}
+
+		/// Commit to a sealed order without disclosing it. `hash` is the hash of
+		/// `(pair, side, volume, ratio, salt)`; `CommitDeposit` of the native coin is held
+		/// until the order is revealed.
+		///
+		/// Unrevealed commitments are refunded once the reveal window closes, less
+		/// `CommitPenalty`, which goes to `AccountVault`.
+		#[weight = 10_000]
+		fn exchange_order_commit(
+			origin,
+			hash: T::Hash) {
+			let caller = ensure_signed(origin)?;
+			let deposit = T::CommitDeposit::get();
+			Self::transfer_coin(caller.clone(), Self::account_operation(), deposit)?;
+
+			let created = <system::Module<T>>::block_number();
+			let index = <CommitmentCount>::get();
+			<CommitmentCount>::put(index + 1);
+			<Commitments<T>>::insert(index, CommitmentInfo {
+				commitment_id: index,
+				owner: caller.clone(),
+				hash,
+				deposit,
+				created
+			});
+			let deadline = created + T::RevealDelay::get() + T::RevealWindow::get();
+			<CommitmentExpiry<T>>::append(deadline + One::one(), index);
+
+			Self::deposit_event(RawEvent::OrderCommitted(caller, index));
+		}
+
+		/// Reveal a committed order. Allowed from `RevealDelay` blocks after the commit
+		/// for `RevealWindow` blocks. The deposit is returned, the order's volume is
+		/// escrowed and the order is placed like any limit order.
+		#[weight = <Module<T>>::placement_weight().saturating_add(T::DbWeight::get().reads_writes(2, 2))]
+		fn exchange_order_reveal(
+			origin,
+			commitment_id: CommitmentIndex,
+			pair: PairIndex,
+			side: OrderSide,
+			volume: BalanceOf<T>,
+			ratio: Price,
+			salt: T::Hash) {
+			let caller = ensure_signed(origin)?;
+			let commitment = <Commitments<T>>::get(commitment_id).ok_or(Error::<T>::CommitmentNotFound)?;
+			ensure!(commitment.owner == caller, Error::<T>::NotOrderOwner);
+			let now = <system::Module<T>>::block_number();
+			let opens = commitment.created + T::RevealDelay::get();
+			ensure!(now >= opens, Error::<T>::RevealTooEarly);
+			ensure!(now <= opens + T::RevealWindow::get(), Error::<T>::RevealWindowClosed);
+			ensure!(T::Hashing::hash_of(&(pair, side, volume, ratio, salt)) == commitment.hash, Error::<T>::RevealMismatch);
+
+			Self::ensure_active(pair)?;
+			ensure!(!volume.is_zero(), Error::<T>::ZeroVolume);
+			ensure!(!ratio.is_zero(), Error::<T>::ZeroRatio);
+			Self::ensure_market_params(side, pair, volume, ratio)?;
+			Self::lock_escrow(side, pair, caller.clone(), volume)?;
+
+			<Commitments<T>>::remove(commitment_id);
+			Self::pay_out(AssetId::Native, caller.clone(), commitment.deposit);
+			let order_id = Self::place_order(side, pair, caller.clone(), volume, ratio, now, None, None);
+
+			Self::deposit_event(RawEvent::CommitmentRevealed(caller, commitment_id, order_id));
+		}
+
+		fn on_runtime_upgrade() -> Weight {
+			let mut version = <StorageVersion>::get();
+			let mut weight: Weight = 0;
+			if version == 0 {
+				weight = weight.saturating_add(Self::migrate_order_expiry());
+				version = 1;
+			}
+			if version == 1 {
+				weight = weight.saturating_add(Self::migrate_order_reserve());
+				version = 2;
+			}
+			if version == 2 {
+				weight = weight.saturating_add(Self::migrate_order_client_ids());
+				version = 3;
+			}
+			if version == 3 {
+				weight = weight.saturating_add(Self::migrate_to_price_levels());
+				version = 4;
+			}
+			if version == 4 {
+				weight = weight.saturating_add(Self::migrate_to_fixed_prices());
+				version = 5;
+			}
+			if version == 5 {
+				weight = weight.saturating_add(Self::migrate_pair_market_params());
+				version = 6;
+			}
+			if version == 6 {
+				weight = weight.saturating_add(Self::migrate_to_unified_books());
+				version = 7;
+			}
+			if version == 7 {
+				weight = weight.saturating_add(Self::migrate_pair_index());
+				version = 8;
+			}
+			if version == 8 {
+				weight = weight.saturating_add(Self::migrate_pair_metadata());
+				version = 9;
+			}
+			if version == 9 {
+				weight = weight.saturating_add(Self::migrate_order_sequence());
+				version = 10;
+			}
+			<StorageVersion>::put(version);
+			weight
+		}
+
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			Self::sweep_expired_orders(now)
+				.saturating_add(Self::sweep_expired_commitments(now))
+				.saturating_add(Self::sweep_delisted_pairs())
+				.saturating_add(T::MatchingWeightBudget::get())
+		}
+
+		fn on_finalize(now: T::BlockNumber) {
+
+			let _now = now;
+			let mut budget = T::MatchingWeightBudget::get();
+			Self::match_books(&mut budget);
+			Self::trigger_conditional_orders(&mut budget);
+
+		}
+
+	}
+}
+
+impl<T: Trait> Module<T> {
+
+	fn transfer_coin(
+		from: AccountIdOf<T>,
+		to: AccountIdOf<T>,
+		value:BalanceOf<T>) -> DispatchResult {
+
+		<T as pallet_token::Trait>::Currency::transfer(&from, &to, value, AllowDeath)
+	}
+
+	/// Move `amount` of `asset` from one account to another.
+	fn transfer_asset(
+		asset: AssetId,
+		from: AccountIdOf<T>,
+		to: AccountIdOf<T>,
+		amount: BalanceOf<T>) -> DispatchResult {
+		match asset {
+			AssetId::Native => Self::transfer_coin(from, to, amount),
+			AssetId::Token(token) => {
+				ensure!(<Token::Module<T>>::get_balance(token, from.clone()) >= amount, Error::<T>::InsufficientAmount);
+				<Token::Module<T>>::transfer_(token, from, to, amount)
+			},
+		}
+	}
+
+	/// Free balance of `asset` held by `account`.
+	fn asset_balance(asset: AssetId, account: &AccountIdOf<T>) -> BalanceOf<T> {
+		match asset {
+			AssetId::Native => <T as pallet_token::Trait>::Currency::free_balance(account),
+			AssetId::Token(token) => <Token::Module<T>>::get_balance(token, account.clone()),
+		}
+	}
+
+	/// Pay `amount` of `asset` out of `AccountOperation`, which holds every escrow and
+	/// deposit. Payouts run from hooks and matching with nothing to roll back, so a
+	/// failure, which would mean the exchange account is short, is not propagated.
+	fn pay_out(asset: AssetId, to: AccountIdOf<T>, amount: BalanceOf<T>) -> () {
+		if amount.is_zero() {
+			return;
+		}
+		let _ = Self::transfer_asset(asset, Self::account_operation(), to, amount);
+	}
+
+	fn ensure_amendment(
+		volume: BalanceOf<T>,
+		ratio: Price,
+		new_volume: BalanceOf<T>,
+		new_ratio: Price) -> DispatchResult {
+		ensure!(!new_volume.is_zero(), Error::<T>::ZeroVolume);
+		ensure!(new_volume != volume || new_ratio != ratio, Error::<T>::NothingToAmend);
+		Ok(())
+	}
+
+	/// Move escrow of `asset` so that `account` has `new` locked instead of `old`.
+	fn settle_escrow(
+		asset: AssetId,
+		account: AccountIdOf<T>,
+		old: BalanceOf<T>,
+		new: BalanceOf<T>) -> DispatchResult {
+		let exchange = Self::account_operation();
+		if new > old {
+			Self::transfer_asset(asset, account, exchange, new - old)?;
+		} else if new < old {
+			Self::transfer_asset(asset, exchange, account, old - new)?;
+		}
+		Ok(())
+	}
+
+
+	/// `amount * numerator / denominator`, rounded up or down. `None` on a zero
+	/// denominator or a result that does not fit a balance.
+	fn mul_div(
+		amount: BalanceOf<T>,
+		numerator: u128,
+		denominator: u128,
+		round_up: bool) -> Option<BalanceOf<T>> {
+		if denominator == 0 {
+			return None;
+		}
+		let amount: u128 = amount.saturated_into();
+		let result = match amount.checked_mul(numerator) {
+			Some(product) if round_up && product % denominator != 0 => product / denominator + 1,
+			Some(product) => product / denominator,
+			None => {
+				// Past 128 bits `multiply_by_rational` rounds to the nearest unit, so settle
+				// the direction against the exact 256-bit product.
+				let product = helpers_128bit::to_big_uint(amount).mul(&helpers_128bit::to_big_uint(numerator));
+				let scaled = |quotient: u128| {
+					helpers_128bit::to_big_uint(quotient).mul(&helpers_128bit::to_big_uint(denominator))
+				};
+				let mut quotient = helpers_128bit::multiply_by_rational(amount, numerator, denominator).ok()?;
+				if scaled(quotient) > product {
+					quotient -= 1;
+				}
+				if round_up && scaled(quotient) < product {
+					quotient = quotient.checked_add(1)?;
+				}
+				quotient
+			},
+		};
+		result.try_into().ok()
+	}
+
+	/// Base and target volumes exchanged when a buy holding `buy_volume` of base meets a
+	/// sell holding `sell_volume` of target at `price`.
+	///
+	/// Target is rounded down to what the buyer can afford. The base leg is then rounded
+	/// in favour of `maker`: up when the seller rests on the book, down when the buyer
+	/// does.
+	fn fill_amounts(
+		buy_volume: BalanceOf<T>,
+		sell_volume: BalanceOf<T>,
+		price: Price,
+		maker: OrderSide) -> (BalanceOf<T>, BalanceOf<T>) {
+		let none = (Zero::zero(), Zero::zero());
+		let affordable = match Self::mul_div(buy_volume, Price::accuracy(), price.into_inner(), false) {
+			Some(affordable) => affordable,
+			None => return none,
+		};
+		let target_volume = if affordable < sell_volume { affordable } else { sell_volume };
+		let round_up = maker == OrderSide::Sell;
+		match Self::mul_div(target_volume, price.into_inner(), Price::accuracy(), round_up) {
+			Some(base_volume) if base_volume <= buy_volume => (base_volume, target_volume),
+			_ => none,
+		}
+	}
+
+	/// Whether `a` is a better price than `b` for orders resting on `side`.
+	fn better_price(side: OrderSide, a: Price, b: Price) -> bool {
+		match side {
+			OrderSide::Buy => a > b,
+			OrderSide::Sell => a < b,
+		}
+	}
+
+	/// Queue an order at the back of the `price` level, opening the level if needed.
+	fn level_insert(order: &OrderRef, price: Price) -> () {
+		<PriceLevels>::mutate((order.side, order.pair), |levels| {
+			if !levels.contains(&price) {
+				let at = levels.iter()
+					.position(|level| Self::better_price(order.side, price, *level))
+					.unwrap_or(levels.len());
+				levels.insert(at, price);
+			}
+		});
+		<LevelOrders>::append((order.side, order.pair, price), order.order_id);
+	}
+
+	/// Take an order out of the `price` level, closing the level once it is empty.
+	fn level_remove(order: &OrderRef, price: Price) -> () {
+		let key = (order.side, order.pair, price);
+		let mut queue = <LevelOrders>::get(&key);
+		queue.retain(|id| *id != order.order_id);
+		if queue.is_empty() {
+			<LevelOrders>::remove(&key);
+			<PriceLevels>::mutate((order.side, order.pair), |levels| levels.retain(|level| *level != price));
+		} else {
+			<LevelOrders>::insert(&key, queue);
+		}
+	}
+
+	/// Move an order to the back of the queue at `to`, as if it had just arrived.
+	fn requeue(order: &OrderRef, from: Price, to: Price) -> () {
+		Self::level_remove(order, from);
+		Self::level_insert(order, to);
+		let sequence = Self::next_sequence();
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => <BuyOrder<T>>::mutate(key, |o| o.sequence = sequence),
+			OrderSide::Sell => <SellOrder<T>>::mutate(key, |o| o.sequence = sequence),
+		}
+	}
+
+	/// Take the next arrival sequence number.
+	fn next_sequence() -> u64 {
+		let sequence = <OrderSequence>::get();
+		<OrderSequence>::put(sequence + 1);
+		sequence
+	}
+
+	/// Head of the best price level on one side of a book.
+	fn best_order(side: OrderSide, pair: PairIndex) -> Option<(Price, BuyOrderIndex)> {
+		let price = *<PriceLevels>::get((side, pair)).first()?;
+		let order_id = *<LevelOrders>::get((side, pair, price)).first()?;
+		Some((price, order_id))
+	}
+
+	/// Owner, remaining volume and ratio of a resting order.
+	fn order_terms(order: &OrderRef) -> Option<(AccountIdOf<T>, BalanceOf<T>, Price)> {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => if <BuyOrder<T>>::contains_key(key) {
+				let o = <BuyOrder<T>>::get(key);
+				return Some((o.buyer, o.volume, o.ratio));
+			},
+			OrderSide::Sell => if <SellOrder<T>>::contains_key(key) {
+				let o = <SellOrder<T>>::get(key);
+				return Some((o.seller, o.volume, o.ratio));
+			},
+		}
+		None
+	}
+
+	/// Arrival sequence number of a resting order, lower for the earlier arrival.
+	fn order_sequence_of(order: &OrderRef) -> u64 {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => <BuyOrder<T>>::get(key).sequence,
+			OrderSide::Sell => <SellOrder<T>>::get(key).sequence,
+		}
+	}
+
+	/// Set the remaining volume of a resting order, dropping it once it is empty. A
+	/// dropped order keeps its place in its order group, which the fill's `note_fill`
+	/// then resolves.
+	fn set_order_volume(order: &OrderRef, volume: BalanceOf<T>) -> () {
+		if Self::order_terms(order).is_none() {
+			return;
+		}
+		if volume <= Self::minimum_volume() && Self::replenish(order, volume) {
+			return;
+		}
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => if volume.is_zero() {
+				Self::remove_buy_order(order.pair, order.order_id);
+			} else {
+				<BuyOrder<T>>::mutate(key, |v| v.volume = volume);
+			},
+			OrderSide::Sell => if volume.is_zero() {
+				Self::remove_sell_order(order.pair, order.order_id);
+			} else {
+				<SellOrder<T>>::mutate(key, |v| v.volume = volume);
+			},
+		}
+	}
+
+	/// Asset escrowed by `side` orders on a pair: base for buys, target for sells.
+	fn escrow_asset(side: OrderSide, pair: PairIndex) -> Option<AssetId> {
+		let info = <Pair<T>>::get(pair)?;
+		match side {
+			OrderSide::Buy => Some(info.base),
+			OrderSide::Sell => Some(info.target),
+		}
+	}
+
+	/// Move `amount` of the asset escrowed by `side` orders from `account` into
+	/// `AccountOperation`.
+	fn lock_escrow(
+		side: OrderSide,
+		pair: PairIndex,
+		account: AccountIdOf<T>,
+		amount: BalanceOf<T>) -> DispatchResult {
+		let asset = Self::escrow_asset(side, pair).ok_or(Error::<T>::PairNotFound)?;
+		Self::transfer_asset(asset, account, Self::account_operation(), amount)
+	}
+
+	/// Send `amount` of the asset escrowed by `side` orders back to `account`.
+	fn release_escrow(
+		side: OrderSide,
+		pair: PairIndex,
+		account: AccountIdOf<T>,
+		amount: BalanceOf<T>) -> () {
+		if let Some(asset) = Self::escrow_asset(side, pair) {
+			Self::pay_out(asset, account, amount);
+		}
+	}
+
+	/// Cross an incoming order against the opposite side of the book at the resting
+	/// orders' prices. `volume` is in the asset the taker gives (base for buys, target
+	/// for sells) and `limit` bounds the price the taker accepts.
+	///
+	/// Levels are read best first and the walk stops at the first one that does not
+	/// cross `limit`, or after `MaxFillsPerOrder` resting orders.
+	///
+	/// Returns the volume the taker gave and the volume it received. With `execute`
+	/// unset nothing is written, so the same walk doubles as a simulation.
+	fn match_incoming(
+		side: OrderSide,
+		pair: PairIndex,
+		taker: &AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		limit: Option<Price>,
+		execute: bool) -> (BalanceOf<T>, BalanceOf<T>) {
+		let maker_side = side.opposite();
+		let max_fills = T::MaxFillsPerOrder::get();
+		let mut fills: u32 = 0;
+		let mut remaining = volume;
+		let mut received: BalanceOf<T> = Zero::zero();
+
+		'levels: for price in <PriceLevels>::get((maker_side, pair)) {
+			if remaining.is_zero() || fills >= max_fills {
+				break;
+			}
+			let crosses = match (side, limit) {
+				(_, None) => true,
+				(OrderSide::Buy, Some(limit)) => price <= limit,
+				(OrderSide::Sell, Some(limit)) => price >= limit,
+			};
+			if !crosses {
+				break;
+			}
+
+			for order_id in <LevelOrders>::get((maker_side, pair, price)) {
+				if remaining.is_zero() || fills >= max_fills {
+					break 'levels;
+				}
+				let maker_order = OrderRef { side: maker_side, pair, order_id };
+				let (maker, maker_volume, _) = match Self::order_terms(&maker_order) {
+					Some(terms) => terms,
+					None => continue,
+				};
+				fills += 1;
+
+				let (base_volume, target_volume) = match side {
+					OrderSide::Buy => Self::fill_amounts(remaining, maker_volume, price, maker_side),
+					OrderSide::Sell => Self::fill_amounts(maker_volume, remaining, price, maker_side),
+				};
+				if base_volume.is_zero() || target_volume.is_zero() {
+					continue;
+				}
+				let (taker_gives, maker_gives) = match side {
+					OrderSide::Buy => (base_volume, target_volume),
+					OrderSide::Sell => (target_volume, base_volume),
+				};
+				remaining -= taker_gives;
+				received += maker_gives;
+
+				if execute {
+					let (seller, buyer) = match side {
+						OrderSide::Buy => (maker, taker.clone()),
+						OrderSide::Sell => (taker.clone(), maker),
+					};
+					Self::swap(pair, seller, buyer, base_volume, target_volume, price);
+					let maker_left = maker_volume - maker_gives;
+					Self::deposit_fill(&maker_order, maker_gives);
+					let (_, reserve) = Self::order_reserve(&maker_order);
+					let complete = maker_left <= Self::minimum_volume() && reserve.is_zero();
+					Self::set_order_volume(&maker_order, maker_left);
+					Self::note_fill(GroupLeg::Resting(maker_order), complete);
+				}
+			}
+		}
+
+		(volume - remaining, received)
+	}
+
+	/// Tick size, lot size and minimum notional of a pair.
+	fn market_params(pair: PairIndex) -> Option<(Price, BalanceOf<T>, BalanceOf<T>)> {
+		<Pair<T>>::get(pair).map(|p| (p.tick_size, p.lot_size, p.min_notional))
+	}
+
+	fn ensure_tick(pair: PairIndex, ratio: Price) -> DispatchResult {
+		let (tick_size, _, _) = Self::market_params(pair).ok_or(Error::<T>::PairNotFound)?;
+		ensure!(tick_size.is_zero() || ratio.into_inner() % tick_size.into_inner() == 0, Error::<T>::InvalidTickSize);
+		Ok(())
+	}
+
+	/// Check an order against its pair's tick size, lot size and minimum notional.
+	/// `volume` is in base for buys and in target for sells, as escrowed. Lot size
+	/// applies to target on both sides, so a buy is converted at `ratio`.
+	fn ensure_market_params(
+		side: OrderSide,
+		pair: PairIndex,
+		volume: BalanceOf<T>,
+		ratio: Price) -> DispatchResult {
+		Self::ensure_tick(pair, ratio)?;
+		let (_, lot_size, min_notional) = Self::market_params(pair).ok_or(Error::<T>::PairNotFound)?;
+		let target_volume = match side {
+			OrderSide::Buy => Self::mul_div(volume, Price::accuracy(), ratio.into_inner(), false),
+			OrderSide::Sell => Some(volume),
+		};
+		ensure!(
+			lot_size.is_zero() || target_volume.map_or(false, |target_volume| (target_volume % lot_size).is_zero()),
+			Error::<T>::InvalidLotSize);
+		let notional = match side {
+			OrderSide::Buy => Some(volume),
+			OrderSide::Sell => Self::mul_div(volume, ratio.into_inner(), Price::accuracy(), false),
+		};
+		// A notional too large for a balance is certainly above the minimum.
+		ensure!(notional.map_or(true, |notional| notional >= min_notional), Error::<T>::BelowMinimumNotional);
+		Ok(())
+	}
+
+	/// Reject post-only orders that would take liquidity and fill-or-kill orders the
+	/// book can not absorb. Runs before any escrow moves.
+	fn ensure_time_in_force(
+		side: OrderSide,
+		pair: PairIndex,
+		volume: BalanceOf<T>,
+		ratio: Price,
+		time_in_force: TimeInForce) -> DispatchResult {
+		ensure!(!ratio.is_zero(), Error::<T>::ZeroRatio);
+		let nobody = Self::account_operation();
+		match time_in_force {
+			TimeInForce::PostOnly => {
+				let (taken, _) = Self::match_incoming(side, pair, &nobody, volume, Some(ratio), false);
+				ensure!(taken.is_zero(), Error::<T>::WouldCrossBook);
+			},
+			TimeInForce::FillOrKill => {
+				let (taken, _) = Self::match_incoming(side, pair, &nobody, volume, Some(ratio), false);
+				ensure!(volume - taken <= Self::minimum_volume(), Error::<T>::CannotFillOrKill);
+			},
+			_ => (),
+		}
+		Ok(())
+	}
+
+	/// Orders that would trade on placement, or market stops, are refused on auction
+	/// pairs, which only trade at the clearing.
+	fn ensure_not_auction(pair: PairIndex) -> DispatchResult {
+		ensure!(Self::matching_mode(pair) != MatchingMode::Auction, Error::<T>::ImmediateOrderOnAuction);
+		Ok(())
+	}
+
+	/// Match an escrowed immediate-or-cancel / fill-or-kill order and refund whatever
+	/// did not fill.
+	fn execute_immediate(
+		side: OrderSide,
+		pair: PairIndex,
+		taker: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: Price,
+		client_id: Option<ClientOrderId>) -> () {
+		let (filled, _) = Self::match_incoming(side, pair, &taker, volume, Some(ratio), true);
+		let unfilled = volume - filled;
+		Self::release_escrow(side, pair, taker.clone(), unfilled);
+		Self::deposit_event(RawEvent::ImmediateOrderClosed(taker, pair, side, filled, unfilled, client_id));
+	}
+
+	/// Volume-weighted price of a fill in which the taker gave `given` and received
+	/// `received`.
+	fn average_price(side: OrderSide, given: BalanceOf<T>, received: BalanceOf<T>) -> Price {
+		let (base_volume, target_volume) = match side {
+			OrderSide::Buy => (given, received),
+			OrderSide::Sell => (received, given),
+		};
+		Price::checked_from_rational(base_volume.saturated_into::<u128>(), target_volume.saturated_into::<u128>())
+			.unwrap_or_else(Zero::zero)
+	}
+
+	/// Take liquidity from the book at the best available prices, bounded by
+	/// `worst_ratio`.
+	///
+	/// With `allow_partial` the order walks the book only through prices no worse than
+	/// `worst_ratio` and refunds the rest. Otherwise it must fill completely with a
+	/// volume-weighted price no worse than `worst_ratio`, or it is rejected.
+	fn execute_market(
+		side: OrderSide,
+		pair: PairIndex,
+		taker: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		worst_ratio: Price,
+		allow_partial: bool) -> DispatchResult {
+		ensure!(!volume.is_zero(), Error::<T>::ZeroVolume);
+		ensure!(!worst_ratio.is_zero(), Error::<T>::ZeroRatio);
+
+		let limit = if allow_partial {
+			Some(worst_ratio)
+		} else {
+			let (given, received) = Self::match_incoming(side, pair, &taker, volume, None, false);
+			ensure!(volume - given <= Self::minimum_volume(), Error::<T>::InsufficientLiquidity);
+			let average = Self::average_price(side, given, received);
+			let within = match side {
+				OrderSide::Buy => average <= worst_ratio,
+				OrderSide::Sell => average >= worst_ratio,
+			};
+			ensure!(within, Error::<T>::SlippageExceeded);
+			None
+		};
+
+		Self::lock_escrow(side, pair, taker.clone(), volume)?;
+		let (given, received) = Self::match_incoming(side, pair, &taker, volume, limit, true);
+		let unfilled = volume - given;
+		Self::release_escrow(side, pair, taker.clone(), unfilled);
+
+		Self::deposit_event(RawEvent::MarketOrderExecuted(taker, pair, side, given, received, unfilled));
+		Ok(())
+	}
+
+	/// Trade along `path`, leg by leg, as market orders without a price limit. Each
+	/// leg trades what the previous one actually paid out to the taker.
+	#[transactional]
+	fn execute_route(
+		taker: AccountIdOf<T>,
+		from: AssetId,
+		path: Vec<PairIndex>,
+		volume: BalanceOf<T>,
+		min_output: BalanceOf<T>) -> DispatchResult {
+		let mut asset = from;
+		let mut amount = volume;
+		let mut spent = None;
+		for pair in path {
+			let info = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?;
+			ensure!(info.active, Error::<T>::TradingPairPaused);
+			let (side, next) = if info.base == asset {
+				(OrderSide::Buy, info.target)
+			} else if info.target == asset {
+				(OrderSide::Sell, info.base)
+			} else {
+				return Err(Error::<T>::InvalidRoute.into());
+			};
+
+			let held = Self::asset_balance(next, &taker);
+			Self::lock_escrow(side, pair, taker.clone(), amount)?;
+			let (given, received) = Self::match_incoming(side, pair, &taker, amount, None, true);
+			ensure!(!received.is_zero(), Error::<T>::InsufficientLiquidity);
+			Self::release_escrow(side, pair, taker.clone(), amount - given);
+			Self::deposit_event(RawEvent::MarketOrderExecuted(
+				taker.clone(), pair, side, given, received, amount - given));
+
+			spent = spent.or(Some(given));
+			asset = next;
+			amount = Self::asset_balance(next, &taker).saturating_sub(held);
+		}
+		ensure!(amount >= min_output, Error::<T>::SlippageExceeded);
+
+		Self::deposit_event(RawEvent::RouteExecuted(taker, from, asset, spent.unwrap_or(volume), amount));
+		Ok(())
+	}
+
+	/// Rest a limit order whose escrow is already held, first matching it against
+	/// the book if the pair is in continuous mode. A remainder at or below
+	/// `MinimumVolume` left by matching is refunded instead of resting. Returns the
+	/// id of the resting order, if any.
+	fn place_order(
+		side: OrderSide,
+		pair: PairIndex,
+		owner: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: Price,
+		created: T::BlockNumber,
+		expires_at: Option<T::BlockNumber>,
+		client_id: Option<ClientOrderId>) -> Option<BuyOrderIndex> {
+		let mut volume = volume;
+		if Self::matching_mode(pair) == MatchingMode::Continuous {
+			let (given, received) = Self::match_incoming(side, pair, &owner, volume, Some(ratio), true);
+			if !given.is_zero() {
+				Self::deposit_event(
+					RawEvent::OrderMatchedOnPlacement(owner.clone(), pair, side, given, received, client_id));
+				volume -= given;
+				if volume <= Self::minimum_volume() {
+					if !volume.is_zero() {
+						Self::release_escrow(side, pair, owner, volume);
+					}
+					return None;
+				}
+			}
+		}
+
+		Some(Self::insert_order(side, pair, owner, volume, ratio, created, expires_at, client_id))
+	}
+
+	/// Refuse a limit order that would cross a continuous pair's book where it can not
+	/// be matched on placement.
+	fn ensure_no_cross(side: OrderSide, pair: PairIndex, volume: BalanceOf<T>, ratio: Price) -> DispatchResult {
+		if Self::matching_mode(pair) == MatchingMode::Continuous {
+			let (taken, _) = Self::match_incoming(side, pair, &Self::account_operation(), volume, Some(ratio), false);
+			ensure!(taken.is_zero(), Error::<T>::WouldCrossBook);
+		}
+		Ok(())
+	}
+
+	/// Put an already escrowed limit order at the back of its book and return its id.
+	fn insert_order(
+		side: OrderSide,
+		pair: PairIndex,
+		owner: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: Price,
+		created: T::BlockNumber,
+		expires_at: Option<T::BlockNumber>,
+		client_id: Option<ClientOrderId>) -> BuyOrderIndex {
+		let owner_account = owner.clone();
+		let index = match side {
+			OrderSide::Buy => {
+				let index = <BuyOrderCount>::get(pair);
+				<BuyOrder<T>>::insert((pair, index), BuyOrderInfo {
+					order_id: index,
+					pair,
+					buyer: owner,
+					volume,
+					ratio,
+					display: Zero::zero(),
+					reserve: Zero::zero(),
+					created,
+					sequence: Self::next_sequence(),
+					expires_at,
+					client_id
+				});
+				<BuyOrderCount>::insert(pair, index + 1);
+				index
+			},
+			OrderSide::Sell => {
+				let index = <SellOrderCount>::get(pair);
+				<SellOrder<T>>::insert((pair, index), SellOrderInfo {
+					order_id: index,
+					pair,
+					seller: owner,
+					volume,
+					ratio,
+					display: Zero::zero(),
+					reserve: Zero::zero(),
+					created,
+					sequence: Self::next_sequence(),
+					expires_at,
+					client_id
+				});
+				<SellOrderCount>::insert(pair, index + 1);
+				index
+			},
+		};
+		let order = OrderRef { side, pair, order_id: index };
+		Self::level_insert(&order, ratio);
+		if let Some(client_id) = client_id {
+			<ClientOrders<T>>::insert(&owner_account, client_id, order.clone());
+		}
+		<AccountOrders<T>>::append(&owner_account, order.clone());
+		Self::schedule_expiry(expires_at, order);
+
+		match side {
+			OrderSide::Buy => Self::deposit_event(
+				RawEvent::BuyOrderCreated(index, pair, ratio, volume, owner_account, client_id)),
+			OrderSide::Sell => Self::deposit_event(
+				RawEvent::SellOrderCreated(index, pair, ratio, volume, owner_account, client_id)),
+		}
+		index
+	}
+
+	fn pair_exists(pair: PairIndex) -> bool {
+		<Pair<T>>::contains_key(pair)
+	}
+
+	/// Open a pair trading `target` against `base`, managed by `banker`.
+	fn create_pair(
+		base: AssetId,
+		target: AssetId,
+		banker: AccountIdOf<T>) -> Result<PairIndex, DispatchError> {
+		ensure!(base != target, Error::<T>::IdenticalAssets);
+		ensure!(!<PairOf>::contains_key((base, target)), Error::<T>::PairExists);
+		let base_metadata = Self::listable_metadata(base)?;
+		let target_metadata = Self::listable_metadata(target)?;
+		let created = <system::Module<T>>::block_number();
+		let active: bool = true;
+
+		let index = PairCount::get();
+		PairCount::put(index + 1);
+		<PairOf>::insert((base, target), index);
+
+		<Pair<T>>::insert(index, PairInfo {
+			base,
+			target,
+			banker,
+			active,
 			created,
-		});		
-		Self::deposit_event(RawEvent::TradeCreated(index, pair, ratio, buyer_volume));			
+			tick_size: Zero::zero(),
+			lot_size: Zero::zero(),
+			min_notional: Zero::zero(),
+			base_metadata,
+			target_metadata
+		});
+
+		Self::deposit_event(RawEvent::PairCreated(index, created));
+		Ok(index)
+	}
+
+	/// Metadata of an asset that a new pair may trade.
+	fn listable_metadata(asset: AssetId) -> Result<AssetMetadata, DispatchError> {
+		let metadata = T::Assets::metadata(asset).ok_or(Error::<T>::UnknownAsset)?;
+		ensure!(T::Assets::is_enabled(asset), Error::<T>::AssetDisabled);
+		Ok(metadata)
+	}
+
+	fn pair_active(pair: PairIndex) -> bool {
+		<Pair<T>>::get(pair).map_or(false, |info| info.active)
+	}
+
+	fn ensure_active(pair: PairIndex) -> DispatchResult {
+		let info = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?;
+		ensure!(info.active, Error::<T>::TradingPairPaused);
+		Ok(())
+	}
+
+	fn set_pair_active(pair: PairIndex, active: bool) -> DispatchResult {
+		<Pair<T>>::try_mutate(pair, |info| -> DispatchResult {
+			let info = info.as_mut().ok_or(Error::<T>::PairNotFound)?;
+			info.active = active;
+			Ok(())
+		})
+	}
+
+	/// Display slice and hidden reserve of a resting order.
+	fn order_reserve(order: &OrderRef) -> (BalanceOf<T>, BalanceOf<T>) {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => {
+				let o = <BuyOrder<T>>::get(key);
+				(o.display, o.reserve)
+			},
+			OrderSide::Sell => {
+				let o = <SellOrder<T>>::get(key);
+				(o.display, o.reserve)
+			},
+		}
+	}
+
+	fn set_order_reserve(
+		order: &OrderRef,
+		volume: BalanceOf<T>,
+		display: BalanceOf<T>,
+		reserve: BalanceOf<T>) -> () {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => <BuyOrder<T>>::mutate(key, |v| {
+				v.volume = volume;
+				v.display = display;
+				v.reserve = reserve;
+			}),
+			OrderSide::Sell => <SellOrder<T>>::mutate(key, |v| {
+				v.volume = volume;
+				v.display = display;
+				v.reserve = reserve;
+			}),
+		}
+	}
+
+	/// Refill the visible slice of an iceberg order from its reserve and send it to
+	/// the back of the queue. Returns `false` if there is no reserve left.
+	fn replenish(order: &OrderRef, visible: BalanceOf<T>) -> bool {
+		let (display, reserve) = Self::order_reserve(order);
+		if reserve.is_zero() {
+			return false;
+		}
+		let slice = if display < reserve { display } else { reserve };
+		let visible = visible.saturating_add(slice);
+		Self::set_order_reserve(order, visible, display, reserve - slice);
+		if let Some((_, _, price)) = Self::order_terms(order) {
+			Self::requeue(order, price, price);
+		}
+		Self::deposit_event(RawEvent::IcebergReplenished(order.pair, order.side, order.order_id, visible));
+		true
+	}
+
+	/// Part of a trade leg paid out after the 0.1% exchange fee.
+	fn after_fee(amount: BalanceOf<T>) -> BalanceOf<T> {
+		Permill::from_parts(999000) * amount
+	}
+
+	/// Preview an order of `volume` taking liquidity on `side`, bounded by `limit`,
+	/// without writing to storage. Walks the book exactly as order placement does.
+	pub fn quote(
+		pair: PairIndex,
+		side: OrderSide,
+		volume: BalanceOf<T>,
+		limit: Option<Price>) -> Quote<BalanceOf<T>> {
+		let nobody = Self::account_operation();
+		let (filled, received) = Self::match_incoming(side, pair, &nobody, volume, limit, false);
+		Quote {
+			filled,
+			received: Self::after_fee(received),
+			average_price: Self::average_price(side, filled, received),
+			fee: received - Self::after_fee(received),
+			unfilled: volume - filled
+		}
+	}
+
+	/// Visible volume per price level on one side of a book, best price first.
+	/// Iceberg reserves are not included.
+	pub fn order_book_depth(side: OrderSide, pair: PairIndex) -> Vec<(Price, BalanceOf<T>)> {
+		<PriceLevels>::get((side, pair)).into_iter().map(|price| {
+			let volume = <LevelOrders>::get((side, pair, price)).into_iter()
+				.filter_map(|order_id| Self::order_terms(&OrderRef { side, pair, order_id }))
+				.fold(Zero::zero(), |total: BalanceOf<T>, (_, volume, _)| total.saturating_add(volume));
+			(price, volume)
+		}).collect()
+	}
+
+	/// Listing proposals awaiting approval, oldest first.
+	pub fn pending_listings() -> Vec<ListingProposalOf<T>> {
+		let mut proposals: Vec<ListingProposalOf<T>> = <ListingProposals<T>>::iter()
+			.map(|(_, proposal)| proposal)
+			.collect();
+		proposals.sort_by_key(|proposal| proposal.proposal_id);
+		proposals
 	}
 
-	fn match_orders() -> () {
+	/// Last traded price of a pair, if it has traded at all.
+	fn last_trade_price(pair: PairIndex) -> Option<Price> {
+		let count = <TradeCount>::get(pair);
+		if count == 0 { return None; }
+		<Trades<T>>::get((pair, count - 1)).map(|t| t.ratio)
+	}
 
+	/// Release conditional orders whose trigger was crossed by the pair's last trade
+	/// price, as market or limit orders. Runs after the block's matching pass, on
+	/// what is left of its `budget`; orders that do not fit stay pending and are
+	/// checked again next block, starting from the saved cursor. Market stops stay
+	/// pending while their pair is matched by auction.
+	fn trigger_conditional_orders(budget: &mut Weight) -> () {
 		let all_pairs = <PairCount>::get();
-		let min_volume = <MinimumVolume<T>>::get();
-		let pre_: BalanceOf<T> = 1000000.into();
-		let rationalisation: BalanceOf<T> = pre_* pre_;	
-		
-		for pair in 0..all_pairs {
+		if all_pairs == 0 {
+			return;
+		}
+		let now = <system::Module<T>>::block_number();
+		let check = T::DbWeight::get().reads(1);
+		let market = Self::taker_weight();
+		let (start, mut from) = <TriggerCursor>::take().map_or((0, 0), |cursor| (cursor.pair, cursor.order_id));
+
+		for step in 0..all_pairs {
+			let pair = (start + step) % all_pairs;
+			let mut pending = <ConditionalOrderList>::get(pair);
+			pending.retain(|order_id| *order_id >= from);
+			from = 0;
+			if pending.is_empty() || !Self::pair_active(pair) {
+				continue;
+			}
+			let last_price = match Self::last_trade_price(pair) {
+				Some(price) => price,
+				None => continue,
+			};
+			let mode = Self::matching_mode(pair);
+			let auction = mode == MatchingMode::Auction;
+			// Stop-limits match on release on continuous pairs, like market stops.
+			let limit_cost = if mode == MatchingMode::Continuous { market } else { Self::match_step_weight() };
+
+			for order_id in pending {
+				if *budget < check {
+					<TriggerCursor>::put(TriggerCursorInfo { pair, order_id });
+					return;
+				}
+				*budget -= check;
+				let order = match <ConditionalOrder<T>>::get((pair, order_id)) {
+					Some(order) => order,
+					None => continue,
+				};
+				let triggered = match order.side {
+					OrderSide::Buy => last_price >= order.trigger,
+					OrderSide::Sell => last_price <= order.trigger,
+				};
+				if !triggered || (auction && order.limit.is_none()) {
+					continue;
+				}
+				let cost = if order.limit.is_some() { limit_cost } else { market };
+				if *budget < cost {
+					<TriggerCursor>::put(TriggerCursorInfo { pair, order_id });
+					return;
+				}
+				*budget -= cost;
+
+				// A bracket stop takes over what is left of its take-profit's escrow.
+				let volume = match <SharedEscrow>::get((pair, order_id)) {
+					Some(take_profit) => Self::take_order(&take_profit)
+						.map_or_else(Zero::zero, |(_, escrowed, _)| escrowed),
+					None => order.volume,
+				};
+				Self::note_fill(GroupLeg::Conditional(pair, order_id), true);
+				Self::remove_conditional_order(pair, order_id);
+				Self::deposit_event(RawEvent::ConditionalOrderTriggered(pair, order_id, last_price));
+
+				let resting = match order.limit {
+					_ if volume.is_zero() => None,
+					Some(ratio) => Self::place_order(order.side, pair, order.owner, volume, ratio, now, None, None),
+					None => {
+						let (given, _) = Self::match_incoming(
+							order.side, pair, &order.owner, volume, None, true);
+						Self::release_escrow(order.side, pair, order.owner, volume - given);
+						None
+					},
+				};
+				Self::deposit_event(RawEvent::ConditionalOrderConverted(pair, order_id, resting));
+			}
+		}
+	}
 
-			let _buy_orders = <BuyOrderList>::get(pair);
-			let _sell_orders = <SellOrderList>::get(pair);
-
-			let buy_orders_iter = _buy_orders.iter();				
-
-			for buy_item_number in buy_orders_iter {
-				let buy_item = <BuyOrder<T>>::get((pair, buy_item_number));
-
-				let sell_orders_iter = _sell_orders.iter();
-
-				for sell_item_number in sell_orders_iter {
-
-					let sell_item = <SellOrder<T>>::get((pair, sell_item_number));
-
-					let buy_account = &buy_item.buyer;
-					let buy_order_id = buy_item.order_id;
-					let buy_volume = buy_item.volume;
-					let buy_price = buy_item.ratio;	
-					let buy_created = &buy_item.created;		
-
-					let sell_account = &sell_item.seller;
-					let sell_order_id = sell_item.order_id;
-					let sell_volume = sell_item.volume;
-					let sell_price = sell_item.ratio;
-					let sell_created = &sell_item.created;	
-
-					let trade_price = buy_price;
-
-					if buy_price >= sell_price && buy_volume >= min_volume && sell_volume >= min_volume {
-						
-						let buy_implied_volume = buy_volume * buy_price / rationalisation;
-						let sell_implied_volume = sell_price / sell_volume * rationalisation;
-						let base_volume;
-						let target_volume;						
-
-						if sell_volume < buy_implied_volume {
-							base_volume = sell_implied_volume;
-							target_volume = sell_volume;
-						} else {
-							base_volume = buy_implied_volume;
-							target_volume = buy_volume;
-						}
-
-						let new_buyer_volume = buy_volume - base_volume;
-						let new_seller_volume =  sell_volume - target_volume;
-
-						BuyOrder::<T>::mutate((pair, buy_order_id), |v| *v = BuyOrderInfo {
-							order_id: buy_order_id,
-							pair: pair,
-							buyer: buy_account.clone(),
-							volume: new_buyer_volume,
-							ratio: buy_price,
-							created: *buy_created
-						});
-						
-						SellOrder::<T>::mutate((pair, sell_order_id), |v| *v = SellOrderInfo {
-							order_id: sell_order_id,
-							pair: pair,
-							seller: sell_account.clone(),
-							volume: new_seller_volume,
-							ratio: sell_price,
-							created: *sell_created
-						});									
-
-						// SWAP
-						Self::swap(pair.clone(), 
-							sell_account.clone(), 
-							buy_account.clone(), 
-							base_volume.clone(), 
-							target_volume.clone(), 
-							trade_price);	
-						// Reduce volume
-					} else {
-						
-					}
-					
-
-					// if buy_price >= sell_price {
-
-					// 	let matched_price = buy_price;
-					// 	let pair: u128 = pair.into();
-					// 	let mut seller_volume = sell_volume;
-					// 	let mut buyer_volume =  buy_volume;
-					// 	let mut matched_volume = sell_volume;
-
-					// 	if buy_volume >= sell_volume {
-					// 		matched_volume = sell_volume
-					// 		seller_volume -= sell_volume;
-					// 		buyer_volume = buy_volume - sell_volume;
-
-					// 	} else if buy_volume < sell_volume && buy_volume > min_volume {
-					// 		matched_volume = buy_volume;
-					// 		seller_volume -= buy_volume;
-					// 		buyer_volume = sell_volume - buy_volume;	
-
-					// 	} 
-					
-																					
-
-					// } 						
-				}	
-
-			}
-		
-
-			let buy_orders_iter2 = _buy_orders.iter();	
-			for buy_item_number in buy_orders_iter2 {
-				let buy_item = <BuyOrder<T>>::get((pair, buy_item_number));
-				let buy_order_id = buy_item.order_id;
-				let buy_volume = buy_item.volume;					
-
-				if buy_volume <= min_volume {					
-					let mut buy_order_list = <BuyOrderList>::get(pair);
-					match buy_order_list.binary_search(&buy_item_number) {
-
-						Ok(index) => {
-							buy_order_list.remove(index);
-							<BuyOrderList>::insert(pair, buy_order_list);							
-							<BuyOrder<T>>::remove((pair, buy_order_id));
-						},
-						Err(_) => {
-							
-						},
-					}
-				}		
-			}				
-
-			let sell_orders_iter2 = _sell_orders.iter();
-
-			for sell_item_number in sell_orders_iter2 {
-				let sell_item = <SellOrder<T>>::get((pair, sell_item_number));	
-				let sell_order_id = sell_item.order_id;
-				let sell_volume = sell_item.volume;	
-				
-				if sell_volume <= min_volume {					
-					let mut sell_order_list = <SellOrderList>::get(pair);
-					match sell_order_list.binary_search(&sell_item_number) {
-
-						Ok(index) => {
-							sell_order_list.remove(index);
-							<SellOrderList>::insert(pair, sell_order_list);							
-							<SellOrder<T>>::remove((pair, sell_order_id));
-						},
-						Err(_) => {
-							
-						},
+	/// Store an already escrowed conditional order and return its id.
+	fn insert_conditional_order(
+		pair: PairIndex,
+		owner: AccountIdOf<T>,
+		side: OrderSide,
+		volume: BalanceOf<T>,
+		trigger: Price,
+		limit: Option<Price>,
+		created: T::BlockNumber) -> ConditionalOrderIndex {
+		let index = <ConditionalOrderCount>::get(pair);
+		<ConditionalOrder<T>>::insert((pair, index), ConditionalOrderInfo {
+			order_id: index,
+			pair,
+			owner,
+			side,
+			volume,
+			trigger,
+			limit,
+			created
+		});
+		<ConditionalOrderList>::append(pair, index);
+		<ConditionalOrderCount>::insert(pair, index + 1);
+		index
+	}
+
+	fn remove_conditional_order(pair: PairIndex, order_id: ConditionalOrderIndex) -> () {
+		<ConditionalOrder<T>>::remove((pair, order_id));
+		<ConditionalOrderList>::mutate(pair, |list| list.retain(|id| *id != order_id));
+		<SharedEscrow>::remove((pair, order_id));
+		Self::detach_leg(GroupLeg::Conditional(pair, order_id));
+	}
+
+	/// Remove a conditional order and refund its escrow, unless it is a bracket stop
+	/// sharing its take-profit's escrow. Returns the owner and the refunded volume.
+	fn cancel_conditional_order(
+		pair: PairIndex,
+		order_id: ConditionalOrderIndex) -> Option<(AccountIdOf<T>, BalanceOf<T>)> {
+		let order = <ConditionalOrder<T>>::get((pair, order_id))?;
+		let refunded = if <SharedEscrow>::contains_key((pair, order_id)) { Zero::zero() } else { order.volume };
+		Self::remove_conditional_order(pair, order_id);
+		Self::release_escrow(order.side, pair, order.owner.clone(), refunded);
+		Some((order.owner, refunded))
+	}
+
+	/// Cancel the bracket stops sharing the escrow of `order`, which is leaving the book.
+	fn cancel_shared_stops(order: &OrderRef) -> () {
+		let group = match <OrderGroupOf>::get(GroupLeg::Resting(order.clone())).and_then(<OrderGroup<T>>::get) {
+			Some(group) => group,
+			None => return,
+		};
+		for leg in group.legs {
+			if let GroupLeg::Conditional(pair, order_id) = leg {
+				if <SharedEscrow>::get((pair, order_id)).as_ref() != Some(order) {
+					continue;
+				}
+				if let Some((owner, refunded)) = Self::cancel_conditional_order(pair, order_id) {
+					Self::deposit_event(RawEvent::ConditionalOrderCancelled(owner, pair, order_id, refunded));
+				}
+			}
+		}
+	}
+
+	/// Owner, side and pair of an order group leg that is still live.
+	fn leg_terms(leg: &GroupLeg) -> Option<(AccountIdOf<T>, OrderSide, PairIndex)> {
+		match leg {
+			GroupLeg::Resting(order) => Self::order_terms(order).map(|(owner, _, _)| (owner, order.side, order.pair)),
+			GroupLeg::Conditional(pair, order_id) =>
+				<ConditionalOrder<T>>::get((*pair, *order_id)).map(|o| (o.owner, o.side, *pair)),
+		}
+	}
+
+	fn link_orders(
+		owner: AccountIdOf<T>,
+		legs: Vec<GroupLeg>,
+		policy: GroupPolicy) -> Result<OrderGroupIndex, DispatchError> {
+		ensure!(legs.len() >= 2, Error::<T>::TooFewLegs);
+		ensure!(legs.len() <= T::MaxGroupLegs::get() as usize, Error::<T>::TooManyLegs);
+		let mut sides: Vec<(PairIndex, OrderSide)> = Vec::new();
+		for (i, leg) in legs.iter().enumerate() {
+			let (leg_owner, side, pair) = Self::leg_terms(leg).ok_or(Error::<T>::OrderNotFound)?;
+			ensure!(leg_owner == owner, Error::<T>::NotOrderOwner);
+			// Legs on opposite sides of a pair could fill against each other.
+			ensure!(!sides.contains(&(pair, side.opposite())), Error::<T>::OpposingLegs);
+			sides.push((pair, side));
+			ensure!(!<OrderGroupOf>::contains_key(leg), Error::<T>::OrderAlreadyLinked);
+			ensure!(!legs[..i].contains(leg), Error::<T>::OrderAlreadyLinked);
+		}
+
+		let index = <OrderGroupCount>::get();
+		for leg in legs.iter() {
+			<OrderGroupOf>::insert(leg, index);
+		}
+		<OrderGroup<T>>::insert(index, OrderGroupInfo {
+			group_id: index,
+			owner: owner.clone(),
+			legs,
+			policy,
+			created: <system::Module<T>>::block_number()
+		});
+		<OrderGroupCount>::put(index + 1);
+
+		Self::deposit_event(RawEvent::OrderGroupCreated(owner, index));
+		Ok(index)
+	}
+
+	/// Record a fill on `leg`; resolves its group if the group's policy is met.
+	fn note_fill(leg: GroupLeg, complete: bool) -> () {
+		if let Some(group_id) = <OrderGroupOf>::get(&leg) {
+			let policy = match <OrderGroup<T>>::get(group_id) {
+				Some(group) => group.policy,
+				None => return,
+			};
+			if policy == GroupPolicy::AnyFill || complete {
+				Self::resolve_group(group_id, leg);
+			}
+		}
+	}
+
+	/// Close a group after `filled` executed: every other leg is cancelled and its
+	/// escrow refunded.
+	fn resolve_group(group_id: OrderGroupIndex, filled: GroupLeg) -> () {
+		let group = match <OrderGroup<T>>::take(group_id) {
+			Some(group) => group,
+			None => return,
+		};
+		for leg in group.legs.iter() {
+			<OrderGroupOf>::remove(leg);
+		}
+		for leg in group.legs.iter().filter(|leg| **leg != filled) {
+			match leg {
+				GroupLeg::Resting(order) => {
+					Self::refund_order(order);
+				},
+				GroupLeg::Conditional(pair, order_id) => {
+					Self::cancel_conditional_order(*pair, *order_id);
+				},
+			}
+		}
+		Self::deposit_event(RawEvent::OrderGroupResolved(group_id, filled));
+	}
+
+	/// Drop a leg that left the book without resolving its group, e.g. on cancel or
+	/// expiry. A group left with a single leg is dissolved.
+	fn detach_leg(leg: GroupLeg) -> () {
+		let group_id = match <OrderGroupOf>::take(&leg) {
+			Some(group_id) => group_id,
+			None => return,
+		};
+		let mut group = match <OrderGroup<T>>::get(group_id) {
+			Some(group) => group,
+			None => return,
+		};
+		group.legs.retain(|l| *l != leg);
+		if group.legs.len() < 2 {
+			for l in group.legs.iter() {
+				<OrderGroupOf>::remove(l);
+			}
+			<OrderGroup<T>>::remove(group_id);
+			Self::deposit_event(RawEvent::OrderGroupDissolved(group_id));
+		} else {
+			<OrderGroup<T>>::insert(group_id, group);
+		}
+	}
+
+	fn ensure_client_id(account: &AccountIdOf<T>, client_id: Option<ClientOrderId>) -> DispatchResult {
+		if let Some(client_id) = client_id {
+			ensure!(!<ClientOrders<T>>::contains_key(account, client_id), Error::<T>::DuplicateClientOrderId);
+			ensure!(!Self::used_client_order_ids(account).contains(&client_id), Error::<T>::DuplicateClientOrderId);
+		}
+		Ok(())
+	}
+
+	/// Remember that `account` used `client_id`, forgetting its oldest id beyond
+	/// `MaxClientOrderIds`.
+	fn note_client_id(account: &AccountIdOf<T>, client_id: Option<ClientOrderId>) -> () {
+		if let Some(client_id) = client_id {
+			let limit = (T::MaxClientOrderIds::get() as usize).max(1);
+			<UsedClientOrderIds<T>>::mutate(account, |used| {
+				used.push(client_id);
+				if used.len() > limit {
+					let excess = used.len() - limit;
+					used.drain(..excess);
+				}
+			});
+		}
+	}
+
+	fn client_id_of(order: &OrderRef) -> Option<ClientOrderId> {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => <BuyOrder<T>>::get(key).client_id,
+			OrderSide::Sell => <SellOrder<T>>::get(key).client_id,
+		}
+	}
+
+	/// Report a fill of `filled` on a resting order, in the asset it escrows.
+	fn deposit_fill(order: &OrderRef, filled: BalanceOf<T>) -> () {
+		let owner = match Self::order_terms(order) {
+			Some((owner, _, _)) => owner,
+			None => return,
+		};
+		let client_id = Self::client_id_of(order);
+		Self::deposit_event(
+			RawEvent::OrderFilled(owner, order.pair, order.order_id, order.side, filled, client_id));
+	}
+
+	fn ensure_expiry(now: T::BlockNumber, expires_at: Option<T::BlockNumber>) -> DispatchResult {
+		if let Some(expiry) = expires_at {
+			ensure!(expiry > now, Error::<T>::InvalidExpiry);
+		}
+		Ok(())
+	}
+
+	fn schedule_expiry(expires_at: Option<T::BlockNumber>, order: OrderRef) -> () {
+		if let Some(expiry) = expires_at {
+			<OrderExpiry<T>>::append(expiry, order);
+		}
+	}
+
+	/// Owner and expiry of a resting order, if it still exists.
+	fn order_expiry_of(order: &OrderRef) -> Option<(AccountIdOf<T>, Option<T::BlockNumber>)> {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => if <BuyOrder<T>>::contains_key(key) {
+				let o = <BuyOrder<T>>::get(key);
+				return Some((o.buyer, o.expires_at));
+			},
+			OrderSide::Sell => if <SellOrder<T>>::contains_key(key) {
+				let o = <SellOrder<T>>::get(key);
+				return Some((o.seller, o.expires_at));
+			},
+		}
+		None
+	}
+
+	/// Remove a resting order and return its remaining escrow from `AccountOperation`
+	/// to the owner. Returns the owner, refunded volume and client order id, or `None`
+	/// if there is no such order.
+	fn refund_order(order: &OrderRef) -> Option<(AccountIdOf<T>, BalanceOf<T>, Option<ClientOrderId>)> {
+		let asset = Self::escrow_asset(order.side, order.pair)?;
+		let (owner, escrowed, client_id) = Self::take_order(order)?;
+		Self::cancel_shared_stops(order);
+		Self::detach_leg(GroupLeg::Resting(order.clone()));
+		Self::pay_out(asset, owner.clone(), escrowed);
+		Some((owner, escrowed, client_id))
+	}
+
+	/// Remove a resting order, leaving its escrow and order group untouched. Returns
+	/// the owner, the escrow it held and its client order id.
+	fn take_order(order: &OrderRef) -> Option<(AccountIdOf<T>, BalanceOf<T>, Option<ClientOrderId>)> {
+		let key = (order.pair, order.order_id);
+		match order.side {
+			OrderSide::Buy => {
+				if !<BuyOrder<T>>::contains_key(key) { return None; }
+				let o = <BuyOrder<T>>::get(key);
+				Self::remove_buy_order(order.pair, order.order_id);
+				Some((o.buyer, o.volume.saturating_add(o.reserve), o.client_id))
+			},
+			OrderSide::Sell => {
+				if !<SellOrder<T>>::contains_key(key) { return None; }
+				let o = <SellOrder<T>>::get(key);
+				Self::remove_sell_order(order.pair, order.order_id);
+				Some((o.seller, o.volume.saturating_add(o.reserve), o.client_id))
+			},
+		}
+	}
+
+	/// Refund orders expiring at `now`, at most `MaxExpiriesPerBlock` of them. Whatever
+	/// does not fit is carried over to the next block's queue.
+	fn sweep_expired_orders(now: T::BlockNumber) -> Weight {
+		let mut queue = <OrderExpiry<T>>::take(now);
+		let limit = (T::MaxExpiriesPerBlock::get() as usize).max(1);
+		if queue.len() > limit {
+			let carried = queue.split_off(limit);
+			<OrderExpiry<T>>::mutate(now + One::one(), |next| next.extend(carried));
+		}
+
+		let processed = queue.len() as Weight;
+		for order in queue {
+			match Self::order_expiry_of(&order) {
+				Some((_, Some(expiry))) if expiry <= now => (),
+				_ => continue,
+			}
+			if let Some((owner, refunded, client_id)) = Self::refund_order(&order) {
+				Self::deposit_event(
+					RawEvent::OrderExpired(owner, order.pair, order.order_id, order.side, refunded, client_id));
+			}
+		}
+
+		T::DbWeight::get().reads_writes(2, 2)
+			.saturating_add(T::DbWeight::get().reads_writes(4, 4).saturating_mul(processed))
+	}
+
+	/// Refund commitments whose reveal window closed before `now`, keeping
+	/// `CommitPenalty` of each deposit for `AccountVault`.
+	fn sweep_expired_commitments(now: T::BlockNumber) -> Weight {
+		let mut queue = <CommitmentExpiry<T>>::take(now);
+		let limit = (T::MaxCommitmentExpiriesPerBlock::get() as usize).max(1);
+		if queue.len() > limit {
+			let carried = queue.split_off(limit);
+			<CommitmentExpiry<T>>::mutate(now + One::one(), |next| next.extend(carried));
+		}
+
+		let processed = queue.len() as Weight;
+		let vault = Self::account_vault();
+		for commitment_id in queue {
+			let commitment = match <Commitments<T>>::take(commitment_id) {
+				Some(commitment) => commitment,
+				None => continue,
+			};
+			let penalty = T::CommitPenalty::get() * commitment.deposit;
+			let refunded = commitment.deposit - penalty;
+			Self::pay_out(AssetId::Native, vault.clone(), penalty);
+			Self::pay_out(AssetId::Native, commitment.owner.clone(), refunded);
+			Self::deposit_event(RawEvent::CommitmentForfeited(commitment.owner, commitment_id, penalty, refunded));
+		}
+
+		T::DbWeight::get().reads_writes(2, 2)
+			.saturating_add(T::DbWeight::get().reads_writes(3, 3).saturating_mul(processed))
+	}
+
+	/// Refund orders of the oldest delisted pair, at most `MaxDelistRefundsPerBlock`
+	/// of them, and remove the pair once none are left.
+	fn sweep_delisted_pairs() -> Weight {
+		let pair = match <PairDelisting>::get().first() {
+			Some(pair) => *pair,
+			None => return T::DbWeight::get().reads(1),
+		};
+		let limit = (T::MaxDelistRefundsPerBlock::get() as usize).max(1);
+
+		// Levels are read only until `limit` orders are collected.
+		let mut reads: Weight = 0;
+		let mut orders: Vec<OrderRef> = Vec::new();
+		'sides: for side in [OrderSide::Buy, OrderSide::Sell].iter() {
+			if orders.len() >= limit {
+				break;
+			}
+			reads += 1;
+			for price in <PriceLevels>::get((*side, pair)) {
+				if orders.len() >= limit {
+					break 'sides;
+				}
+				reads += 1;
+				for order_id in <LevelOrders>::get((*side, pair, price)) {
+					if orders.len() >= limit {
+						break 'sides;
 					}
-				}					
+					orders.push(OrderRef { side: *side, pair, order_id });
+				}
+			}
+		}
+		let mut processed = orders.len();
+		for order in orders {
+			if let Some((owner, refunded, client_id)) = Self::refund_order(&order) {
+				Self::deposit_event(
+					RawEvent::OrderCancelled(owner, pair, order.order_id, order.side, refunded, client_id));
+			}
+		}
+
+		let conditional = <ConditionalOrderList>::get(pair);
+		for order_id in conditional.iter().take(limit - processed) {
+			if let Some((owner, refunded)) = Self::cancel_conditional_order(pair, *order_id) {
+				Self::deposit_event(RawEvent::ConditionalOrderCancelled(owner, pair, *order_id, refunded));
+			}
+		}
+		processed += conditional.len().min(limit - processed);
+
+		if processed < limit {
+			<PairDelisting>::mutate(|pairs| pairs.retain(|p| *p != pair));
+			if let Some(info) = <Pair<T>>::take(pair) {
+				<PairOf>::remove((info.base, info.target));
+			}
+			<PairMatchingMode>::remove(pair);
+			Self::deposit_event(RawEvent::PairDelisted(pair));
+		}
+
+		T::DbWeight::get().reads_writes(3, 3)
+			.saturating_add(T::DbWeight::get().reads(reads))
+			.saturating_add(T::DbWeight::get().reads_writes(6, 6).saturating_mul(processed as Weight))
+	}
+
+	fn remove_buy_order(pair: PairIndex, order_id: BuyOrderIndex) -> () {
+		let order = OrderRef { side: OrderSide::Buy, pair, order_id };
+		let removed = <BuyOrder<T>>::take((pair, order_id));
+		let owner = removed.buyer;
+		if let Some(client_id) = removed.client_id {
+			<ClientOrders<T>>::remove(&owner, client_id);
+		}
+		Self::level_remove(&order, removed.ratio);
+		<AccountOrders<T>>::mutate(owner, |orders| orders.retain(|o| *o != order));
+	}
 
-				//SellOrderCount::mutate(pair, |v| *v -= 1);	
-			}				
-					
-	
-		}		
+	fn remove_sell_order(pair: PairIndex, order_id: SellOrderIndex) -> () {
+		let order = OrderRef { side: OrderSide::Sell, pair, order_id };
+		let removed = <SellOrder<T>>::take((pair, order_id));
+		let owner = removed.seller;
+		if let Some(client_id) = removed.client_id {
+			<ClientOrders<T>>::remove(&owner, client_id);
+		}
+		Self::level_remove(&order, removed.ratio);
+		<AccountOrders<T>>::mutate(owner, |orders| orders.retain(|o| *o != order));
 	}
 
-	fn swap_native(pair: u128, 
-		seller: AccountIdOf<T>, 
+
+	/// Pay a fill out of `AccountOperation`: `seller_volume` of base to the seller and
+	/// `buyer_volume` of target to the buyer, each less the exchange fee, which goes to
+	/// `AccountVault`.
+	fn swap(pair: PairIndex,
+		seller: AccountIdOf<T>,
 		buyer: AccountIdOf<T>,
-		seller_volume: BalanceOf<T>, 
-		buyer_volume: BalanceOf<T>, 
-		ratio:BalanceOf<T>) -> () {
-		
-		let exchange = Self::account_operation();
+		seller_volume: BalanceOf<T>,
+		buyer_volume: BalanceOf<T>,
+		ratio: Price) -> () {
+
 		let vault = Self::account_vault();
-		let target = <PairNative<T>>::get(pair).unwrap().target;
-		let p999 = sp_runtime::Permill::from_parts(999000);
+		let info = match <Pair<T>>::get(pair) {
+			Some(info) => info,
+			None => return,
+		};
 
-		let base_after_fee = p999 * seller_volume;		
-		Self::transfer_coin(exchange.clone(), seller.clone(), base_after_fee);
-		Self::transfer_coin(exchange.clone(), vault.clone(), seller_volume - base_after_fee);
-		let target_after_fee = p999 * buyer_volume;
-		<Token::Module<T>>::transfer_(target, exchange.clone(), buyer.clone(), target_after_fee);
-		<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), buyer_volume - target_after_fee);
+		let base_after_fee = Self::after_fee(seller_volume);
+		Self::pay_out(info.base, seller.clone(), base_after_fee);
+		Self::pay_out(info.base, vault.clone(), seller_volume - base_after_fee);
+		let target_after_fee = Self::after_fee(buyer_volume);
+		Self::pay_out(info.target, buyer.clone(), target_after_fee);
+		Self::pay_out(info.target, vault.clone(), buyer_volume - target_after_fee);
 
-		let created = <system::Module<T>>::block_number();	
-		let index = <TradeNativeCount>::get(pair);	
+		let created = <system::Module<T>>::block_number();
+		let index = <TradeCount>::get(pair);
 
-		<TradeNatives<T>>::insert((pair, index), TradeNativeInfo {
+		<Trades<T>>::insert((pair, index), TradeInfo {
 			pair,
 			seller,
 			buyer,
 			volume: buyer_volume,
 			ratio,
 			created,
-		});		
-		Self::deposit_event(RawEvent::TradeNativeCreated(index, pair, ratio, buyer_volume));			
+		});
+		<TradeCount>::insert(pair, index + 1);
+		Self::deposit_event(RawEvent::TradeCreated(index, pair, ratio, buyer_volume));
 	}
 
-	fn match_native_orders() -> () {
+	/// Cross every book within `budget`, starting from the saved cursor so that no
+	/// pair is starved when the budget runs out.
+	fn match_books(budget: &mut Weight) -> () {
+		let all_pairs = <PairCount>::get();
+		if all_pairs == 0 {
+			return;
+		}
+		let start = <MatchCursor>::take().map_or(0, |cursor| cursor.pair);
 
-		let all_pairs = <PairNativeCount>::get();
-		let min_volume = <MinimumVolume<T>>::get();
-		let pre_: BalanceOf<T> = 1000000.into();
-		let rationalisation: BalanceOf<T> = pre_* pre_;	
-		
-		for pair in 0..all_pairs {
+		for step in 0..all_pairs {
+			let pair = (start + step) % all_pairs;
+			if !Self::pair_active(pair) {
+				continue;
+			}
+			let suspended = match Self::matching_mode(pair) {
+				MatchingMode::Auction => Self::clear_auction(pair, budget),
+				_ => Self::match_book(pair, budget),
+			};
+			if suspended {
+				<MatchCursor>::put(MatchCursorInfo { pair });
+				Self::deposit_event(RawEvent::MatchingSuspended(pair));
+				return;
+			}
+		}
+	}
 
-			let _buy_orders = <BuyOrderNativeList>::get(pair);
-			let _sell_orders = <SellOrderNativeList>::get(pair);
-
-			let buy_orders_iter = _buy_orders.iter();				
-
-			for buy_item_number in buy_orders_iter {
-				let buy_item = <BuyOrderNative<T>>::get((pair, buy_item_number));
-
-				let sell_orders_iter = _sell_orders.iter();
-
-				for sell_item_number in sell_orders_iter {
-					let sell_item = <SellOrderNative<T>>::get((pair, sell_item_number));
-
-					let buy_account = &buy_item.buyer;
-					let buy_order_id = buy_item.order_id;
-					let buy_volume = buy_item.volume;
-					let buy_price = buy_item.ratio;	
-					let buy_created = &buy_item.created;		
-
-					let sell_account = &sell_item.seller;
-					let sell_order_id = sell_item.order_id;
-					let sell_volume = sell_item.volume;
-					let sell_price = sell_item.ratio;
-					let sell_created = &sell_item.created;	
-
-					let trade_price = buy_price;
-
-					if buy_price >= sell_price && buy_volume >= min_volume && sell_volume >= min_volume {
-						
-						let buy_implied_volume = buy_volume * buy_price / rationalisation;
-						let sell_implied_volume = sell_price / sell_volume * rationalisation;
-						let base_volume;
-						let target_volume;						
-
-						if sell_volume < buy_implied_volume {
-							base_volume = sell_implied_volume;
-							target_volume = sell_volume;
-						} else {
-							base_volume = buy_implied_volume;
-							target_volume = buy_volume;
-						}
-
-						let new_buyer_volume = buy_volume - base_volume;
-						let new_seller_volume =  sell_volume - target_volume;
-
-						BuyOrderNative::<T>::mutate((pair, buy_order_id), |v| *v = BuyOrderNativeInfo {
-							order_id: buy_order_id,
-							pair: pair,
-							buyer: buy_account.clone(),
-							volume: new_buyer_volume,
-							ratio: buy_price,
-							created: *buy_created
-						});
-						
-						SellOrderNative::<T>::mutate((pair, sell_order_id), |v| *v = SellOrderNativeInfo {
-							order_id: sell_order_id,
-							pair: pair,
-							seller: sell_account.clone(),
-							volume: new_seller_volume,
-							ratio: sell_price,
-							created: *sell_created
-						});									
-
-						// SWAP
-						Self::swap_native(pair.clone(), 
-							sell_account.clone(), 
-							buy_account.clone(), 
-							base_volume.clone(), 
-							target_volume.clone(), 
-							trade_price);	
-						// Reduce volume
-					} else {
-						
-					}
-					
+	/// Upper bound on the weight of taking liquidity from one book: `MaxFillsPerOrder`
+	/// matching steps.
+	fn taker_weight() -> Weight {
+		Self::match_step_weight().saturating_mul(T::MaxFillsPerOrder::get() as Weight)
+	}
+
+	/// Upper bound on the weight of placing a limit order: its checks, escrow and
+	/// storage, after matching it on placement on a continuous pair.
+	fn placement_weight() -> Weight {
+		T::DbWeight::get().reads_writes(12, 10).saturating_add(Self::taker_weight())
+	}
+
+	/// Upper bound on the weight of one `match_book` step, including resolving an order
+	/// group of `MaxGroupLegs` legs.
+	fn match_step_weight() -> Weight {
+		T::DbWeight::get().reads_writes(16, 16)
+			.saturating_add(T::DbWeight::get().reads_writes(8, 8).saturating_mul(T::MaxGroupLegs::get() as Weight))
+	}
+
+	/// Crossing orders on one side of an auction, in priority order, stopping at
+	/// prices worse than `bound` or after `limit` orders.
+	fn auction_side(
+		side: OrderSide,
+		pair: PairIndex,
+		bound: Price,
+		limit: usize) -> Vec<(OrderRef, BalanceOf<T>, Price)> {
+		let mut orders = Vec::new();
+		for price in <PriceLevels>::get((side, pair)) {
+			if Self::better_price(side, bound, price) {
+				break;
+			}
+			for order_id in <LevelOrders>::get((side, pair, price)) {
+				if orders.len() >= limit {
+					return orders;
+				}
+				let order = OrderRef { side, pair, order_id };
+				if let Some((_, volume, _)) = Self::order_terms(&order) {
+					orders.push((order, volume, price));
+				}
+			}
+		}
+		orders
+	}
+
+	/// Price among the bid and ask levels that executes the most target volume, ties
+	/// going to the smallest surplus and then to the lowest price.
+	fn clearing_price(
+		bids: &[(OrderRef, BalanceOf<T>, Price)],
+		asks: &[(OrderRef, BalanceOf<T>, Price)]) -> Option<Price> {
+		let mut candidates: Vec<Price> = bids.iter().chain(asks.iter()).map(|(_, _, price)| *price).collect();
+		candidates.sort();
+		candidates.dedup();
+
+		let mut best: Option<(Price, BalanceOf<T>, BalanceOf<T>)> = None;
+		for price in candidates {
+			let demand = bids.iter()
+				.filter(|(_, _, limit)| *limit >= price)
+				.fold(Zero::zero(), |total: BalanceOf<T>, (_, volume, _)| total.saturating_add(
+					Self::mul_div(*volume, Price::accuracy(), price.into_inner(), false).unwrap_or_else(Zero::zero)));
+			let supply = asks.iter()
+				.filter(|(_, _, limit)| *limit <= price)
+				.fold(Zero::zero(), |total: BalanceOf<T>, (_, volume, _)| total.saturating_add(*volume));
+			let executed = if demand < supply { demand } else { supply };
+			let surplus = if demand < supply { supply - demand } else { demand - supply };
+			let better = match best {
+				Some((_, most, least)) => executed > most || (executed == most && surplus < least),
+				None => true,
+			};
+			if better && !executed.is_zero() {
+				best = Some((price, executed, surplus));
+			}
+		}
+		best.map(|(price, _, _)| price)
+	}
 
-					
-				}	
+	/// Clear a pair as a uniform-price auction: every crossing order trades at the
+	/// single price that maximises executed volume, in price-time priority.
+	///
+	/// If `budget` does not cover every crossing order, the auction runs over the
+	/// best-priced ones that fit and the rest wait for the next block. Returns
+	/// whether not even one order on each side fits.
+	fn clear_auction(pair: PairIndex, budget: &mut Weight) -> bool {
+		let (bid, ask) = match (Self::best_order(OrderSide::Buy, pair), Self::best_order(OrderSide::Sell, pair)) {
+			(Some((bid, _)), Some((ask, _))) => (bid, ask),
+			_ => return false,
+		};
+		if bid < ask {
+			return false;
+		}
 
+		let per_order = T::DbWeight::get().reads(2).saturating_add(Self::match_step_weight());
+		let limit = (*budget / per_order.saturating_mul(2).max(1)) as usize;
+		if limit == 0 {
+			return true;
+		}
+		let bids = Self::auction_side(OrderSide::Buy, pair, ask, limit);
+		let asks = Self::auction_side(OrderSide::Sell, pair, bid, limit);
+		*budget = budget.saturating_sub(per_order.saturating_mul((bids.len() + asks.len()) as Weight));
+
+		let price = match Self::clearing_price(&bids, &asks) {
+			Some(price) => price,
+			None => return false,
+		};
+		let bids: Vec<OrderRef> = bids.into_iter().filter(|(_, _, limit)| *limit >= price).map(|(order, _, _)| order).collect();
+		let asks: Vec<OrderRef> = asks.into_iter().filter(|(_, _, limit)| *limit <= price).map(|(order, _, _)| order).collect();
+
+		let min_volume = Self::minimum_volume();
+		let mut base_total: BalanceOf<T> = Zero::zero();
+		let mut target_total: BalanceOf<T> = Zero::zero();
+		let (mut i, mut j) = (0, 0);
+		while i < bids.len() && j < asks.len() {
+			let (buy_order, sell_order) = (&bids[i], &asks[j]);
+			let (buyer, buy_volume, _) = match Self::order_terms(buy_order) {
+				Some(terms) => terms,
+				None => {
+					i += 1;
+					continue;
+				},
+			};
+			let (seller, sell_volume, _) = match Self::order_terms(sell_order) {
+				Some(terms) => terms,
+				None => {
+					j += 1;
+					continue;
+				},
+			};
+			if buy_volume <= min_volume {
+				if !Self::replenish(buy_order, buy_volume) {
+					Self::refund_order(buy_order);
+					i += 1;
+				}
+				continue;
+			}
+			if sell_volume <= min_volume {
+				if !Self::replenish(sell_order, sell_volume) {
+					Self::refund_order(sell_order);
+					j += 1;
+				}
+				continue;
 			}
 
+			let (base_volume, target_volume) = Self::fill_amounts(buy_volume, sell_volume, price, OrderSide::Sell);
+			if target_volume.is_zero() {
+				i += 1;
+				continue;
+			}
+			if base_volume.is_zero() {
+				j += 1;
+				continue;
+			}
+
+			Self::swap(pair, seller, buyer, base_volume, target_volume, price);
+			Self::deposit_fill(buy_order, base_volume);
+			Self::deposit_fill(sell_order, target_volume);
+			base_total = base_total.saturating_add(base_volume);
+			target_total = target_total.saturating_add(target_volume);
+
+			let buy_left = buy_volume - base_volume;
+			let sell_left = sell_volume - target_volume;
+			let buy_complete = buy_left <= min_volume && Self::order_reserve(buy_order).1.is_zero();
+			let sell_complete = sell_left <= min_volume && Self::order_reserve(sell_order).1.is_zero();
+			Self::set_order_volume(buy_order, buy_left);
+			Self::set_order_volume(sell_order, sell_left);
+			Self::note_fill(GroupLeg::Resting(buy_order.clone()), buy_complete);
+			Self::note_fill(GroupLeg::Resting(sell_order.clone()), sell_complete);
+		}
 
-			let buy_orders_iter2 = _buy_orders.iter();	
-			for buy_item_number in buy_orders_iter2 {
-				let buy_item = <BuyOrderNative<T>>::get((pair, buy_item_number));
-				let buy_order_id = buy_item.order_id;
-				let buy_volume = buy_item.volume;					
+		if !target_total.is_zero() {
+			Self::deposit_event(RawEvent::AuctionCleared(pair, price, target_total, base_total));
+		}
+		false
+	}
 
-				if buy_volume <= min_volume {					
-					let mut buy_order_list = <BuyOrderNativeList>::get(pair);
-					match buy_order_list.binary_search(&buy_item_number) {
+	/// Cross the best bid against the best ask until the book no longer crosses.
+	///
+	/// Batch pairs are crossed here once per block. Continuous pairs only reach it
+	/// with orders that rested without matching on placement, such as iceberg
+	/// slices, released stop orders, amended orders and orders left crossed by a
+	/// switch from batch matching.
+	///
+	/// Each fill executes at the price of whichever of the two orders rested first.
+	/// Remainders at or below `MinimumVolume`, or too small to trade at the best
+	/// opposite price, are closed and refunded.
+	///
+	/// Every step is charged against `budget`. Returns whether it ran out before the
+	/// book stopped crossing.
+	fn match_book(pair: PairIndex, budget: &mut Weight) -> bool {
+		let min_volume = Self::minimum_volume();
+		let step = Self::match_step_weight();
+
+		loop {
+			let (bid, buy_id) = match Self::best_order(OrderSide::Buy, pair) {
+				Some(best) => best,
+				None => break,
+			};
+			let (ask, sell_id) = match Self::best_order(OrderSide::Sell, pair) {
+				Some(best) => best,
+				None => break,
+			};
+			if bid < ask {
+				break;
+			}
 
-						Ok(index) => {
-							buy_order_list.remove(index);
-							<BuyOrderNativeList>::insert(pair, buy_order_list);							
-							<BuyOrderNative<T>>::remove((pair, buy_order_id));
-						},
-						Err(_) => {
-							
-						},
-					}
-				}	
-			}				
-
-			let sell_orders_iter2 = _sell_orders.iter();
-
-			for sell_item_number in sell_orders_iter2 {
-				let sell_item = <SellOrderNative<T>>::get((pair, sell_item_number));
-				let sell_order_id = sell_item.order_id;
-				let sell_volume = sell_item.volume;					
-
-				if sell_volume <= min_volume {					
-					let mut sell_order_list = <SellOrderNativeList>::get(pair);
-					match sell_order_list.binary_search(&sell_item_number) {
-
-						Ok(index) => {
-							sell_order_list.remove(index);
-							<SellOrderNativeList>::insert(pair, sell_order_list);							
-							<SellOrderNative<T>>::remove((pair, sell_order_id));
-						},
-						Err(_) => {
-							
-						},
-					}
-				}	
-			}				
-					
-	
-		}		
+			let buy_order = OrderRef { side: OrderSide::Buy, pair, order_id: buy_id };
+			let sell_order = OrderRef { side: OrderSide::Sell, pair, order_id: sell_id };
+			let ask_first = Self::order_sequence_of(&sell_order) < Self::order_sequence_of(&buy_order);
+			if *budget < step {
+				return true;
+			}
+			*budget -= step;
+
+			let (buyer, buy_volume, _) = match Self::order_terms(&buy_order) {
+				Some(terms) => terms,
+				None => {
+					Self::level_remove(&buy_order, bid);
+					continue;
+				},
+			};
+			let (seller, sell_volume, _) = match Self::order_terms(&sell_order) {
+				Some(terms) => terms,
+				None => {
+					Self::level_remove(&sell_order, ask);
+					continue;
+				},
+			};
+
+			if buy_volume <= min_volume {
+				if !Self::replenish(&buy_order, buy_volume) {
+					Self::refund_order(&buy_order);
+				}
+				continue;
+			}
+			if sell_volume <= min_volume {
+				if !Self::replenish(&sell_order, sell_volume) {
+					Self::refund_order(&sell_order);
+				}
+				continue;
+			}
+
+			let (price, maker) = if ask_first { (ask, OrderSide::Sell) } else { (bid, OrderSide::Buy) };
+			let (base_volume, target_volume) = Self::fill_amounts(buy_volume, sell_volume, price, maker);
+			if target_volume.is_zero() {
+				// The bid can not afford a single unit of target at this price.
+				Self::refund_order(&buy_order);
+				continue;
+			}
+			if base_volume.is_zero() {
+				// The ask is worth less than a single unit of base at this price.
+				Self::refund_order(&sell_order);
+				continue;
+			}
+
+			Self::swap(pair, seller, buyer, base_volume, target_volume, price);
+			Self::deposit_fill(&buy_order, base_volume);
+			Self::deposit_fill(&sell_order, target_volume);
+
+			// Both orders are settled before either group resolves, so a resolution
+			// never refunds an order that is about to be written back.
+			let buy_left = buy_volume - base_volume;
+			let sell_left = sell_volume - target_volume;
+			let buy_complete = buy_left <= min_volume && Self::order_reserve(&buy_order).1.is_zero();
+			let sell_complete = sell_left <= min_volume && Self::order_reserve(&sell_order).1.is_zero();
+			Self::set_order_volume(&buy_order, buy_left);
+			Self::set_order_volume(&sell_order, sell_left);
+			Self::note_fill(GroupLeg::Resting(buy_order), buy_complete);
+			Self::note_fill(GroupLeg::Resting(sell_order), sell_complete);
+		}
+
+		false
+	}
+
+	/// Re-encode every value of a map under a new layout, keeping its keys. Returns
+	/// the number of values translated.
+	fn translate_legacy<O: Decode, V: Encode>(item: &[u8], f: impl Fn(O) -> V) -> Weight {
+		let values: Vec<(Vec<u8>, O)> = migration::StorageIterator::<O>::new(b"Exchange", item).collect();
+		let count = values.len() as Weight;
+		for (key, value) in values {
+			migration::put_storage_value(b"Exchange", item, &key, f(value));
+		}
+		count
+	}
+
+	/// Give orders of the first release an unset expiry.
+	fn migrate_order_expiry() -> Weight {
+		let mut orders: Weight = 0;
+		for item in LEGACY_ORDER_ITEMS.iter() {
+			orders += Self::translate_legacy::<LegacyOrderInfoV0Of<T>, _>(item, |o| LegacyOrderInfoV1 {
+				order_id: o.order_id,
+				pair: o.pair,
+				owner: o.owner,
+				volume: o.volume,
+				ratio: o.ratio,
+				created: o.created,
+				expires_at: None
+			});
+		}
+		T::DbWeight::get().reads_writes(orders, orders)
+	}
+
+	/// Give existing orders an empty iceberg reserve, showing their whole volume.
+	fn migrate_order_reserve() -> Weight {
+		let mut orders: Weight = 0;
+		for item in LEGACY_ORDER_ITEMS.iter() {
+			orders += Self::translate_legacy::<LegacyOrderInfoV1Of<T>, _>(item, |o| LegacyOrderInfoV2 {
+				order_id: o.order_id,
+				pair: o.pair,
+				owner: o.owner,
+				volume: o.volume,
+				ratio: o.ratio,
+				display: Zero::zero(),
+				reserve: Zero::zero(),
+				created: o.created,
+				expires_at: o.expires_at
+			});
+		}
+		T::DbWeight::get().reads_writes(orders, orders)
+	}
+
+	/// Give existing orders no client order id.
+	fn migrate_order_client_ids() -> Weight {
+		let mut orders: Weight = 0;
+		for item in LEGACY_ORDER_ITEMS.iter() {
+			orders += Self::translate_legacy::<LegacyOrderInfoV2Of<T>, _>(item, |o| LegacyOrderInfo {
+				order_id: o.order_id,
+				pair: o.pair,
+				owner: o.owner,
+				volume: o.volume,
+				ratio: o.ratio,
+				display: o.display,
+				reserve: o.reserve,
+				created: o.created,
+				expires_at: o.expires_at,
+				client_id: None
+			});
+		}
+		T::DbWeight::get().reads_writes(orders, orders)
+	}
+
+	/// Queue existing orders on price levels, best price first and oldest first within
+	/// a level, and drop the flat order lists they replace.
+	fn migrate_to_price_levels() -> Weight {
+		let books = [
+			(&b"BuyOrder"[..], BookKind::Token, OrderSide::Buy),
+			(b"SellOrder", BookKind::Token, OrderSide::Sell),
+			(b"BuyOrderNative", BookKind::Native, OrderSide::Buy),
+			(b"SellOrderNative", BookKind::Native, OrderSide::Sell),
+		];
+		let mut orders: Vec<(BookKind, OrderSide, PairIndex, BalanceOf<T>, BuyOrderIndex)> = Vec::new();
+		for (item, book, side) in books.iter() {
+			for (_, o) in migration::StorageIterator::<LegacyOrderInfoOf<T, BalanceOf<T>>>::new(b"Exchange", item) {
+				orders.push((*book, *side, o.pair, o.ratio, o.order_id));
+			}
+		}
+		orders.sort_by_key(|(_, _, _, _, order_id)| *order_id);
+
+		let mut levels: BTreeMap<Vec<u8>, Vec<BalanceOf<T>>> = BTreeMap::new();
+		let mut queues: BTreeMap<Vec<u8>, Vec<BuyOrderIndex>> = BTreeMap::new();
+		for (book, side, pair, ratio, order_id) in orders.iter() {
+			let book_levels = levels.entry(Blake2_128Concat::hash(&(book, side, pair).encode())).or_insert_with(Vec::new);
+			if !book_levels.contains(ratio) {
+				let position = book_levels.iter()
+					.position(|level| match side {
+						OrderSide::Buy => level < ratio,
+						OrderSide::Sell => level > ratio,
+					})
+					.unwrap_or(book_levels.len());
+				book_levels.insert(position, *ratio);
+			}
+			queues.entry(Blake2_128Concat::hash(&(book, side, pair, ratio).encode()))
+				.or_insert_with(Vec::new)
+				.push(*order_id);
+		}
+		for (key, book_levels) in levels {
+			migration::put_storage_value(b"Exchange", b"PriceLevels", &key, book_levels);
+		}
+		for (key, queue) in queues {
+			migration::put_storage_value(b"Exchange", b"LevelOrders", &key, queue);
+		}
+
+		for item in [&b"BuyOrderList"[..], b"SellOrderList", b"BuyOrderNativeList", b"SellOrderNativeList"].iter() {
+			migration::remove_storage_prefix(b"Exchange", item, &[]);
+		}
+
+		T::MaximumBlockWeight::get()
+	}
+
+	/// Price of a ratio stored before prices were `Price`.
+	fn legacy_price(ratio: BalanceOf<T>) -> Price {
+		Price::saturating_from_rational(ratio.saturated_into::<u128>(), LEGACY_PRICE_SCALE)
+	}
+
+	/// Convert stored ratios, trigger prices and price levels to `Price`.
+	fn migrate_to_fixed_prices() -> Weight {
+		for item in LEGACY_ORDER_ITEMS.iter() {
+			Self::translate_legacy::<LegacyOrderInfoOf<T, BalanceOf<T>>, _>(item, |o| LegacyOrderInfo {
+				order_id: o.order_id,
+				pair: o.pair,
+				owner: o.owner,
+				volume: o.volume,
+				ratio: Self::legacy_price(o.ratio),
+				display: o.display,
+				reserve: o.reserve,
+				created: o.created,
+				expires_at: o.expires_at,
+				client_id: o.client_id
+			});
+		}
+		for item in [&b"Trades"[..], b"TradeNatives"].iter() {
+			Self::translate_legacy::<LegacyTradeInfoOf<T>, _>(item, |t| TradeInfo {
+				pair: t.pair,
+				seller: t.seller,
+				buyer: t.buyer,
+				volume: t.volume,
+				ratio: Self::legacy_price(t.ratio),
+				created: t.created
+			});
+		}
+		Self::translate_legacy::<LegacyConditionalOrderInfoOf<T>, _>(b"ConditionalOrder", |o| ConditionalOrderInfo {
+			order_id: o.order_id,
+			pair: o.pair,
+			owner: o.owner,
+			side: o.side,
+			volume: o.volume,
+			trigger: Self::legacy_price(o.trigger),
+			limit: o.limit.map(Self::legacy_price),
+			created: o.created
+		});
+
+		// Every old queue is taken before any new one is written, since an old and a
+		// new level key can encode alike.
+		let mut queues: Vec<((BookKind, OrderSide, PairIndex, Price), Vec<BuyOrderIndex>)> = Vec::new();
+		for ((book, side, pair), levels) in Self::drain_legacy::<(BookKind, OrderSide, PairIndex), Vec<BalanceOf<T>>>(b"PriceLevels") {
+			let mut prices: Vec<Price> = Vec::new();
+			for level in levels {
+				let key = Blake2_128Concat::hash(&(book, side, pair, level).encode());
+				let queue: Vec<BuyOrderIndex> = migration::take_storage_value(b"Exchange", b"LevelOrders", &key)
+					.unwrap_or_default();
+				let price = Self::legacy_price(level);
+				queues.push(((book, side, pair, price), queue));
+				prices.push(price);
+			}
+			let key = Blake2_128Concat::hash(&(book, side, pair).encode());
+			migration::put_storage_value(b"Exchange", b"PriceLevels", &key, prices);
+		}
+		for (level, queue) in queues {
+			migration::put_storage_value(b"Exchange", b"LevelOrders", &Blake2_128Concat::hash(&level.encode()), queue);
+		}
+		<MatchCursor>::kill();
+
+		T::MaximumBlockWeight::get()
+	}
+
+	/// Give existing pairs unrestricted market parameters.
+	fn migrate_pair_market_params() -> Weight {
+		let mut pairs = Self::translate_legacy::<LegacyPairInfoV0Of<T>, _>(b"Pair", |p| LegacyPairInfo {
+			base: p.base,
+			target: p.target,
+			banker: p.banker,
+			active: p.active,
+			created: p.created,
+			tick_size: Zero::zero(),
+			lot_size: Zero::zero(),
+			min_notional: Zero::zero()
+		});
+		pairs += Self::translate_legacy::<LegacyPairNativeInfoV0Of<T>, _>(b"PairNative", |p| LegacyPairNativeInfo {
+			target: p.target,
+			banker: p.banker,
+			active: p.active,
+			created: p.created,
+			tick_size: Zero::zero(),
+			lot_size: Zero::zero(),
+			min_notional: Zero::zero()
+		});
+		T::DbWeight::get().reads_writes(pairs, pairs)
+	}
+
+	/// Pair index of a pre-version-7 pair once native pairs follow the token pairs.
+	fn unified_pair(book: BookKind, pair: PairIndex, offset: PairIndex) -> PairIndex {
+		match book {
+			BookKind::Token => pair,
+			BookKind::Native => offset + pair,
+		}
+	}
+
+	fn unified_order(order: LegacyOrderRef, offset: PairIndex) -> OrderRef {
+		OrderRef {
+			side: order.side,
+			pair: Self::unified_pair(order.book, order.pair, offset),
+			order_id: order.order_id
+		}
+	}
+
+	fn unified_leg(leg: LegacyGroupLeg, offset: PairIndex) -> GroupLeg {
+		match leg {
+			LegacyGroupLeg::Resting(order) => GroupLeg::Resting(Self::unified_order(order, offset)),
+			LegacyGroupLeg::Conditional(book, pair, order_id) =>
+				GroupLeg::Conditional(Self::unified_pair(book, pair, offset), order_id),
+		}
+	}
+
+	/// Remove every entry of a map whose key layout changed, decoding its keys.
+	fn drain_legacy<K: Decode, V: Decode>(item: &[u8]) -> Vec<(K, V)> {
+		migration::StorageIterator::<V>::new(b"Exchange", item).drain()
+			.filter_map(|(key, value)| {
+				let mut key = key.get(16..)?;
+				K::decode(&mut key).ok().map(|key| (key, value))
+			})
+			.collect()
+	}
+
+	/// Move native pairs into `Pair`, after the existing token pairs, and carry their
+	/// orders, trades, conditional orders and counters along. Everything keyed by
+	/// `BookKind` is re-keyed by the unified pair index.
+	fn migrate_to_unified_books() -> Weight {
+		let offset = <PairCount>::get();
+		let native_pairs: PairIndex = migration::take_storage_value(b"Exchange", b"PairNativeCount", &[])
+			.unwrap_or_default();
+
+		Self::translate_legacy::<LegacyPairInfoOf<T>, _>(b"Pair", |p| LegacyAssetPairInfo {
+			base: AssetId::Token(p.base),
+			target: AssetId::Token(p.target),
+			banker: p.banker,
+			active: p.active,
+			created: p.created,
+			tick_size: p.tick_size,
+			lot_size: p.lot_size,
+			min_notional: p.min_notional
+		});
+		for (pair, p) in Self::drain_legacy::<PairIndex, LegacyPairNativeInfoOf<T>>(b"PairNative") {
+			let key = Blake2_128Concat::hash(&(offset + pair).encode());
+			migration::put_storage_value(b"Exchange", b"Pair", &key, LegacyAssetPairInfo {
+				base: AssetId::Native,
+				target: AssetId::Token(p.target),
+				banker: p.banker,
+				active: p.active,
+				created: p.created,
+				tick_size: p.tick_size,
+				lot_size: p.lot_size,
+				min_notional: p.min_notional
+			});
+		}
+		<PairCount>::put(offset + native_pairs);
+
+		for (native, item) in [(&b"BuyOrderNative"[..], &b"BuyOrder"[..]), (b"SellOrderNative", b"SellOrder")].iter() {
+			for ((pair, order_id), mut o) in Self::drain_legacy::<(PairIndex, BuyOrderIndex), LegacyOrderInfoOf<T, Price>>(native) {
+				o.pair = offset + pair;
+				let key = Blake2_128Concat::hash(&(offset + pair, order_id).encode());
+				migration::put_storage_value(b"Exchange", item, &key, o);
+			}
+		}
+		for (pair, count) in Self::drain_legacy::<PairIndex, BuyOrderIndex>(b"BuyOrderNativeCount") {
+			<BuyOrderCount>::insert(offset + pair, count);
+		}
+		for (pair, count) in Self::drain_legacy::<PairIndex, SellOrderIndex>(b"SellOrderNativeCount") {
+			<SellOrderCount>::insert(offset + pair, count);
+		}
+
+		for ((book, side, pair), levels) in Self::drain_legacy::<(BookKind, OrderSide, PairIndex), Vec<Price>>(b"PriceLevels") {
+			let unified = Self::unified_pair(book, pair, offset);
+			for level in levels.iter() {
+				let key = Blake2_128Concat::hash(&(book, side, pair, *level).encode());
+				let queue: Vec<BuyOrderIndex> = migration::take_storage_value(b"Exchange", b"LevelOrders", &key)
+					.unwrap_or_default();
+				<LevelOrders>::insert((side, unified, *level), queue);
+			}
+			<PriceLevels>::insert((side, unified), levels);
+		}
+
+		for ((pair, index), mut t) in Self::drain_legacy::<(PairIndex, TradeIndex), TradeInfoOf<T>>(b"TradeNatives") {
+			t.pair = offset + pair;
+			<Trades<T>>::insert((offset + pair, index), t);
+		}
+		for (pair, count) in Self::drain_legacy::<PairIndex, TradeIndex>(b"TradeNativeCount") {
+			<TradeCount>::insert(offset + pair, count);
+		}
+
+		for ((book, pair, order_id), mut o) in Self::drain_legacy::<(BookKind, PairIndex, ConditionalOrderIndex), ConditionalOrderInfoOf<T>>(b"ConditionalOrder") {
+			o.pair = Self::unified_pair(book, pair, offset);
+			<ConditionalOrder<T>>::insert((o.pair, order_id), o);
+		}
+		for ((book, pair), list) in Self::drain_legacy::<(BookKind, PairIndex), Vec<ConditionalOrderIndex>>(b"ConditionalOrderList") {
+			<ConditionalOrderList>::insert(Self::unified_pair(book, pair, offset), list);
+		}
+		for ((book, pair), count) in Self::drain_legacy::<(BookKind, PairIndex), ConditionalOrderIndex>(b"ConditionalOrderCount") {
+			<ConditionalOrderCount>::insert(Self::unified_pair(book, pair, offset), count);
+		}
+		for ((book, pair), mode) in Self::drain_legacy::<(BookKind, PairIndex), MatchingMode>(b"PairMatchingMode") {
+			<PairMatchingMode>::insert(Self::unified_pair(book, pair, offset), mode);
+		}
+
+		<AccountOrders<T>>::translate::<Vec<LegacyOrderRef>, _>(|_, orders| {
+			Some(orders.into_iter().map(|o| Self::unified_order(o, offset)).collect())
+		});
+		<ClientOrders<T>>::translate::<LegacyOrderRef, _>(|_, _, o| Some(Self::unified_order(o, offset)));
+		<OrderExpiry<T>>::translate::<Vec<LegacyOrderRef>, _>(|_, orders| {
+			Some(orders.into_iter().map(|o| Self::unified_order(o, offset)).collect())
+		});
+		<OrderGroup<T>>::translate::<LegacyOrderGroupInfoOf<T>, _>(|_, g| Some(OrderGroupInfo {
+			group_id: g.group_id,
+			owner: g.owner,
+			legs: g.legs.into_iter().map(|leg| Self::unified_leg(leg, offset)).collect(),
+			policy: g.policy,
+			created: g.created
+		}));
+		for (leg, group_id) in Self::drain_legacy::<LegacyGroupLeg, OrderGroupIndex>(b"OrderGroupOf") {
+			<OrderGroupOf>::insert(Self::unified_leg(leg, offset), group_id);
+		}
+
+		// Sealed native orders commit to a pair index that no longer exists, so their
+		// deposits are returned in full.
+		<Commitments<T>>::translate::<LegacyCommitmentInfoOf<T>, _>(|_, c| {
+			if c.book == BookKind::Native {
+				Self::pay_out(AssetId::Native, c.owner, c.deposit);
+				return None;
+			}
+			Some(CommitmentInfo {
+				commitment_id: c.commitment_id,
+				owner: c.owner,
+				hash: c.hash,
+				deposit: c.deposit,
+				created: c.created
+			})
+		});
+		<MatchCursor>::kill();
+
+		T::MaximumBlockWeight::get()
+	}
+
+	/// Store asset metadata on existing pairs. Assets the registry no longer knows get
+	/// empty metadata.
+	fn migrate_pair_metadata() -> Weight {
+		<Pair<T>>::translate::<LegacyAssetPairInfoOf<T>, _>(|_, p| Some(PairInfo {
+			base: p.base,
+			target: p.target,
+			banker: p.banker,
+			active: p.active,
+			created: p.created,
+			tick_size: p.tick_size,
+			lot_size: p.lot_size,
+			min_notional: p.min_notional,
+			base_metadata: T::Assets::metadata(p.base).unwrap_or_default(),
+			target_metadata: T::Assets::metadata(p.target).unwrap_or_default()
+		}));
+
+		let pairs = <PairCount>::get() as Weight;
+		T::DbWeight::get().reads_writes(pairs, pairs)
 	}
-	
+
+	/// Number existing orders in arrival order, oldest first. Orders placed in the same
+	/// block keep the order of their ids, buys before sells.
+	fn migrate_order_sequence() -> Weight {
+		let mut orders: Vec<(T::BlockNumber, OrderSide, BuyOrderIndex, Vec<u8>, LegacyOrderInfoOf<T, Price>)> = Vec::new();
+		for (item, side) in [(&b"BuyOrder"[..], OrderSide::Buy), (b"SellOrder", OrderSide::Sell)].iter() {
+			for (key, o) in migration::StorageIterator::<LegacyOrderInfoOf<T, Price>>::new(b"Exchange", item) {
+				orders.push((o.created, *side, o.order_id, key, o));
+			}
+		}
+		orders.sort_by(|a, b| (a.0, a.1 == OrderSide::Sell, a.2).cmp(&(b.0, b.1 == OrderSide::Sell, b.2)));
+
+		let count = orders.len() as Weight;
+		for (sequence, (_, side, _, key, o)) in orders.into_iter().enumerate() {
+			let sequence = sequence as u64;
+			match side {
+				OrderSide::Buy => migration::put_storage_value(b"Exchange", b"BuyOrder", &key, BuyOrderInfo {
+					order_id: o.order_id,
+					pair: o.pair,
+					buyer: o.owner,
+					volume: o.volume,
+					ratio: o.ratio,
+					display: o.display,
+					reserve: o.reserve,
+					created: o.created,
+					sequence,
+					expires_at: o.expires_at,
+					client_id: o.client_id
+				}),
+				OrderSide::Sell => migration::put_storage_value(b"Exchange", b"SellOrder", &key, SellOrderInfo {
+					order_id: o.order_id,
+					pair: o.pair,
+					seller: o.owner,
+					volume: o.volume,
+					ratio: o.ratio,
+					display: o.display,
+					reserve: o.reserve,
+					created: o.created,
+					sequence,
+					expires_at: o.expires_at,
+					client_id: o.client_id
+				}),
+			}
+		}
+		<OrderSequence>::put(count);
+
+		T::DbWeight::get().reads_writes(count, count + 1)
+	}
+
+	/// Index existing pairs by `(base, target)`. Where pairs were duplicated, the
+	/// oldest one is indexed.
+	fn migrate_pair_index() -> Weight {
+		let all_pairs = <PairCount>::get();
+		for pair in 0..all_pairs {
+			let key = Blake2_128Concat::hash(&pair.encode());
+			if let Some(info) = migration::get_storage_value::<LegacyAssetPairInfoOf<T>>(b"Exchange", b"Pair", &key) {
+				if !<PairOf>::contains_key((info.base, info.target)) {
+					<PairOf>::insert((info.base, info.target), pair);
+				}
+			}
+		}
+		T::DbWeight::get().reads_writes(all_pairs as Weight * 2, all_pairs as Weight)
+	}
+
 }
 
@@ -0,0 +1,242 @@
+use crate::{AssetId, AssetMetadata, AssetRegistry, GenesisConfig, Module, RawEvent, TokenIndex, Trait};
+use sp_core::H256;
+use frame_support::{
+	impl_outer_event, impl_outer_origin, parameter_types,
+	traits::{Get, OnFinalize, OnInitialize},
+	weights::{RuntimeDbWeight, Weight},
+};
+use frame_system::EnsureRoot;
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::Header, Perbill, Permill,
+};
+use std::cell::RefCell;
+use frame_system as system;
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+mod exchange {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for Test {
+		system<T>,
+		balances<T>,
+		pallet_token<T>,
+		exchange<T>,
+	}
+}
+
+pub const ALICE: u64 = 1;
+pub const BOB: u64 = 2;
+pub const OPERATION: u64 = 10;
+pub const VAULT: u64 = 11;
+
+/// Native coin against token 0.
+pub const PAIR: u128 = 0;
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+	pub const DbWeight: RuntimeDbWeight = RuntimeDbWeight { read: 1, write: 10 };
+}
+
+impl system::Trait for Test {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = DbWeight;
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+parameter_types! {
+	pub const ExistentialDeposit: u64 = 1;
+	pub const MaxLocks: u32 = 50;
+}
+
+impl balances::Trait for Test {
+	type MaxLocks = MaxLocks;
+	type Balance = u64;
+	type DustRemoval = ();
+	type Event = TestEvent;
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+impl pallet_token::Trait for Test {
+	type Event = TestEvent;
+	type Currency = Balances;
+}
+
+/// Tokens 0 and 1 can be listed; token 2 exists but is disabled.
+pub struct TestAssets;
+
+impl AssetRegistry for TestAssets {
+	fn metadata(asset: AssetId) -> Option<AssetMetadata> {
+		match asset {
+			AssetId::Native => Some(AssetMetadata { decimals: 12, symbol: b"DCB".to_vec() }),
+			AssetId::Token(token) if token <= 2 => Some(AssetMetadata { decimals: 6, symbol: vec![b'T', b'0' + token as u8] }),
+			AssetId::Token(_) => None,
+		}
+	}
+
+	fn is_enabled(asset: AssetId) -> bool {
+		asset != AssetId::Token(2)
+	}
+}
+
+thread_local! {
+	static MATCHING_WEIGHT_BUDGET: RefCell<Weight> = RefCell::new(1_000_000);
+	static MAX_EXPIRIES_PER_BLOCK: RefCell<u32> = RefCell::new(10);
+}
+
+/// End-of-block matching budget, adjustable per test with `set_matching_budget`.
+pub struct MatchingWeightBudget;
+
+impl Get<Weight> for MatchingWeightBudget {
+	fn get() -> Weight {
+		MATCHING_WEIGHT_BUDGET.with(|budget| *budget.borrow())
+	}
+}
+
+pub fn set_matching_budget(budget: Weight) {
+	MATCHING_WEIGHT_BUDGET.with(|b| *b.borrow_mut() = budget);
+}
+
+/// Expiry sweep bound, adjustable per test with `set_max_expiries`.
+pub struct MaxExpiriesPerBlock;
+
+impl Get<u32> for MaxExpiriesPerBlock {
+	fn get() -> u32 {
+		MAX_EXPIRIES_PER_BLOCK.with(|limit| *limit.borrow())
+	}
+}
+
+pub fn set_max_expiries(limit: u32) {
+	MAX_EXPIRIES_PER_BLOCK.with(|l| *l.borrow_mut() = limit);
+}
+
+parameter_types! {
+	pub const MaxFillsPerOrder: u32 = 10;
+	pub const MaxClientOrderIds: u32 = 2;
+	pub const MaxGroupLegs: u32 = 3;
+	pub const CommitDeposit: u64 = 10;
+	pub const RevealDelay: u64 = 1;
+	pub const RevealWindow: u64 = 5;
+	pub const CommitPenalty: Permill = Permill::from_percent(10);
+	pub const MaxCommitmentExpiriesPerBlock: u32 = 1;
+	pub const MaxRouteLength: u32 = 3;
+	pub const MaxDelistRefundsPerBlock: u32 = 10;
+	pub const ListingBond: u64 = 100;
+}
+
+impl Trait for Test {
+	type Event = TestEvent;
+	type Currency = Balances;
+	type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+	type MatchingWeightBudget = MatchingWeightBudget;
+	type MaxFillsPerOrder = MaxFillsPerOrder;
+	type MaxClientOrderIds = MaxClientOrderIds;
+	type MaxGroupLegs = MaxGroupLegs;
+	type CommitDeposit = CommitDeposit;
+	type RevealDelay = RevealDelay;
+	type RevealWindow = RevealWindow;
+	type CommitPenalty = CommitPenalty;
+	type MaxCommitmentExpiriesPerBlock = MaxCommitmentExpiriesPerBlock;
+	type MaxRouteLength = MaxRouteLength;
+	type MaxDelistRefundsPerBlock = MaxDelistRefundsPerBlock;
+	type Assets = TestAssets;
+	type CreatePairOrigin = EnsureRoot<u64>;
+	type ParamsOrigin = EnsureRoot<u64>;
+	type PauseOrigin = EnsureRoot<u64>;
+	type AccountsOrigin = EnsureRoot<u64>;
+	type ListingBond = ListingBond;
+	type ListingApproveOrigin = EnsureRoot<u64>;
+}
+
+pub type System = system::Module<Test>;
+pub type Balances = balances::Module<Test>;
+pub type Token = pallet_token::Module<Test>;
+pub type Exchange = Module<Test>;
+
+/// Credit `amount` of `token` to `who`.
+pub fn mint(token: TokenIndex, who: u64, amount: u64) {
+	Token::mint_(token, who, amount);
+}
+
+/// Events deposited by the exchange so far, oldest first.
+pub fn exchange_events() -> Vec<RawEvent<u64, u64, u64>> {
+	System::events().into_iter()
+		.filter_map(|record| match record.event {
+			TestEvent::exchange(event) => Some(event),
+			_ => None,
+		})
+		.collect()
+}
+
+/// Finalize the current block and initialize the following ones up to `n`.
+pub fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		Exchange::on_finalize(System::block_number());
+		System::set_block_number(System::block_number() + 1);
+		Exchange::on_initialize(System::block_number());
+	}
+}
+
+// Build genesis storage with funded accounts and the exchange accounts set, but no
+// pairs, as a chain upgrading from an earlier release would have it.
+pub fn new_legacy_ext() -> sp_io::TestExternalities {
+	set_matching_budget(1_000_000);
+	set_max_expiries(10);
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	balances::GenesisConfig::<Test> {
+		balances: vec![(ALICE, 1_000_000), (BOB, 1_000_000), (OPERATION, 1), (VAULT, 1)],
+	}.assimilate_storage(&mut t).unwrap();
+	GenesisConfig::default().assimilate_storage::<Test>(&mut t).unwrap();
+
+	let mut ext = sp_io::TestExternalities::new(t);
+	ext.execute_with(|| {
+		System::set_block_number(1);
+		Exchange::exchange_accounts(Origin::root(), 1, OPERATION).unwrap();
+		Exchange::exchange_accounts(Origin::root(), 2, VAULT).unwrap();
+	});
+	ext
+}
+
+// As `new_legacy_ext`, with `PAIR` created and both users holding token 0.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut ext = new_legacy_ext();
+	ext.execute_with(|| {
+		Exchange::exchange_pair_create(Origin::root(), AssetId::Native, AssetId::Token(0), Some(ALICE)).unwrap();
+		mint(0, ALICE, 1_000_000);
+		mint(0, BOB, 1_000_000);
+	});
+	ext
+}
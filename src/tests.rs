@@ -1,23 +1,1027 @@
-use crate::{Error, mock::*};
-use frame_support::{assert_ok, assert_noop};
+use crate::{
+	mock::*, AssetId, AssetMetadata, BookKind, BuyOrder, Call, Commitments, ConditionalOrder, Error,
+	GroupLeg, GroupPolicy, LegacyOrderInfo, LegacyOrderInfoV0, LegacyPairInfo, LegacyPairInfoV0,
+	LegacyPairNativeInfo, LegacyTradeInfo, LevelOrders, MatchCursor, MatchingMode, OrderGroup,
+	OrderRef, OrderSequence, OrderSide, Pair, PairCount, PairOf, Price, Quote, RawEvent, SellOrder,
+	SellOrderCount, StorageVersion, TimeInForce, Trades, TriggerCursor, STORAGE_VERSION,
+};
+use frame_support::{
+	assert_noop, assert_ok, Blake2_128Concat, StorageHasher, StorageMap, StorageValue,
+	dispatch::DispatchResult,
+	storage::migration,
+	traits::{Get, OnFinalize, OnInitialize, OnRuntimeUpgrade},
+	weights::{GetDispatchInfo, Weight},
+};
+use parity_scale_codec::Encode;
+use sp_core::H256;
+use sp_runtime::{FixedPointNumber, traits::{BadOrigin, BlakeTwo256, Hash, One, Zero}};
+
+fn buy(who: u64, volume: u64, ratio: Price) -> DispatchResult {
+	Exchange::exchange_order_create_buy(
+		Origin::signed(who), PAIR, volume, ratio, None, TimeInForce::GoodTillCancelled, None)
+}
+
+fn sell(who: u64, volume: u64, ratio: Price) -> DispatchResult {
+	Exchange::exchange_order_create_sell(
+		Origin::signed(who), PAIR, volume, ratio, None, TimeInForce::GoodTillCancelled, None)
+}
+
+fn resting(side: OrderSide, order_id: u128) -> GroupLeg {
+	GroupLeg::Resting(OrderRef { side, pair: PAIR, order_id })
+}
+
+fn sealed(side: OrderSide, volume: u64, ratio: Price) -> H256 {
+	BlakeTwo256::hash_of(&(PAIR, side, volume, ratio, H256::repeat_byte(7)))
+}
+
+fn reveal(who: u64, commitment_id: u128, side: OrderSide, volume: u64, ratio: Price) -> DispatchResult {
+	Exchange::exchange_order_reveal(Origin::signed(who), commitment_id, PAIR, side, volume, ratio, H256::repeat_byte(7))
+}
+
+/// Write `value` under `key` of the map `item` as an earlier release laid it out.
+fn put_legacy<K: Encode, V: Encode>(item: &[u8], key: K, value: V) {
+	migration::put_storage_value(b"Exchange", item, &Blake2_128Concat::hash(&key.encode()), value);
+}
+
+fn legacy_pair_v0() -> LegacyPairInfoV0<u64, u64> {
+	LegacyPairInfoV0 { base: 0, target: 1, banker: ALICE, active: true, created: 1 }
+}
+
+#[test]
+fn cancel_refunds_buy_escrow() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_eq!(Balances::free_balance(ALICE), 999_000);
+
+		assert_ok!(Exchange::exchange_order_cancel_buy(Origin::signed(ALICE), PAIR, 0));
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(Exchange::price_levels((OrderSide::Buy, PAIR)).is_empty());
+	});
+}
+
+#[test]
+fn cancel_requires_owner() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(sell(ALICE, 1_000, Price::one()));
+		assert_noop!(
+			Exchange::exchange_order_cancel_sell(Origin::signed(BOB), PAIR, 0),
+			Error::<Test>::NotOrderOwner
+		);
+	});
+}
+
+#[test]
+fn amend_moves_escrow() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(sell(ALICE, 1_000, Price::one()));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 999_000);
+
+		assert_ok!(Exchange::exchange_order_amend(Origin::signed(ALICE), PAIR, OrderSide::Sell, 0, Some(3_000), None));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 997_000);
+		assert_eq!(Exchange::sell_order((PAIR, 0)).volume, 3_000);
+
+		assert_ok!(Exchange::exchange_order_amend(Origin::signed(ALICE), PAIR, OrderSide::Sell, 0, Some(500), None));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 999_500);
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &OPERATION), 500);
+	});
+}
+
+#[test]
+fn amend_beyond_balance_fails() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_noop!(
+			Exchange::exchange_order_amend(Origin::signed(ALICE), PAIR, OrderSide::Buy, 0, Some(2_000_000), None),
+			balances::Error::<Test, balances::DefaultInstance>::InsufficientBalance
+		);
+	});
+}
+
+#[test]
+fn fill_resolves_order_group() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_ok!(buy(ALICE, 500, Price::saturating_from_rational(1, 2)));
+		assert_ok!(Exchange::exchange_order_link(
+			Origin::signed(ALICE),
+			vec![resting(OrderSide::Buy, 0), resting(OrderSide::Buy, 1)],
+			GroupPolicy::AnyFill));
+		assert_eq!(Balances::free_balance(ALICE), 998_500);
+
+		assert_ok!(sell(BOB, 1_000, Price::one()));
+		Exchange::on_finalize(1);
+
+		assert!(<OrderGroup<Test>>::get(0).is_none());
+		assert!(Exchange::order_group_of(resting(OrderSide::Buy, 0)).is_none());
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 1)));
+		assert_eq!(Balances::free_balance(ALICE), 999_000);
+	});
+}
+
+#[test]
+fn link_rejects_opposing_legs() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_ok!(sell(ALICE, 1_000, Price::saturating_from_integer(2)));
+		assert_noop!(
+			Exchange::exchange_order_link(
+				Origin::signed(ALICE),
+				vec![resting(OrderSide::Buy, 0), resting(OrderSide::Sell, 0)],
+				GroupPolicy::AnyFill),
+			Error::<Test>::OpposingLegs
+		);
+	});
+}
+
+#[test]
+fn link_rejects_too_many_legs() {
+	new_test_ext().execute_with(|| {
+		for _ in 0..4 {
+			assert_ok!(buy(ALICE, 100, Price::one()));
+		}
+		assert_noop!(
+			Exchange::exchange_order_link(
+				Origin::signed(ALICE),
+				(0..4).map(|order_id| resting(OrderSide::Buy, order_id)).collect(),
+				GroupPolicy::AnyFill),
+			Error::<Test>::TooManyLegs
+		);
+		assert_ok!(Exchange::exchange_order_link(
+			Origin::signed(ALICE),
+			(0..3).map(|order_id| resting(OrderSide::Buy, order_id)).collect(),
+			GroupPolicy::AnyFill));
+	});
+}
+
+#[test]
+fn bracket_escrows_volume_once() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_order_bracket(
+			Origin::signed(ALICE), PAIR, OrderSide::Sell, 1_000,
+			Price::saturating_from_integer(2), Price::saturating_from_rational(1, 2), None,
+			GroupPolicy::AnyFill));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 999_000);
+		assert_eq!(Exchange::shared_escrow((PAIR, 0)), Some(OrderRef { side: OrderSide::Sell, pair: PAIR, order_id: 0 }));
+
+		assert_ok!(Exchange::exchange_order_cancel_sell(Origin::signed(ALICE), PAIR, 0));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 1_000_000);
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 0)).is_none());
+		assert!(Exchange::shared_escrow((PAIR, 0)).is_none());
+	});
+}
+
+#[test]
+fn match_rounds_base_up_for_resting_sell() {
+	new_test_ext().execute_with(|| {
+		let third = Price::saturating_from_rational(1, 3);
+		assert_ok!(sell(BOB, 3_001, third));
+		System::set_block_number(2);
+		assert_ok!(buy(ALICE, 1_001, third));
+
+		Exchange::on_finalize(2);
+
+		// 3001 target at a third is worth 1000.33 base; the resting seller gets 1001.
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000 - 1_001);
+		assert_eq!(Exchange::trades((PAIR, 0)).map(|t| t.ratio), Some(third));
+	});
+}
+
+#[test]
+fn match_rounds_base_down_for_resting_buy() {
+	new_test_ext().execute_with(|| {
+		let third = Price::saturating_from_rational(1, 3);
+		assert_ok!(buy(ALICE, 1_001, third));
+		System::set_block_number(2);
+		assert_ok!(sell(BOB, 3_001, third));
+
+		Exchange::on_finalize(2);
+
+		// The resting buyer pays 1000 for 3001 target and keeps one unit on the book.
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		assert_eq!(Exchange::buy_order((PAIR, 0)).volume, 1);
+	});
+}
+
+#[test]
+fn earlier_arrival_in_the_same_block_sets_the_price() {
+	new_test_ext().execute_with(|| {
+		let two = Price::saturating_from_integer(2);
+		assert_ok!(buy(ALICE, 1_000, two));
+		assert_ok!(sell(BOB, 500, Price::one()));
+
+		Exchange::on_finalize(1);
+
+		assert_eq!(Exchange::trades((PAIR, 0)).map(|t| t.ratio), Some(two));
+	});
+}
+
+#[test]
+fn requeued_order_arrives_again() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_ok!(sell(BOB, 1_000, Price::saturating_from_integer(2)));
+		assert!(Exchange::buy_order((PAIR, 0)).sequence < Exchange::sell_order((PAIR, 0)).sequence);
+
+		assert_ok!(Exchange::exchange_order_amend(Origin::signed(ALICE), PAIR, OrderSide::Buy, 0, Some(2_000), None));
+		assert!(Exchange::buy_order((PAIR, 0)).sequence > Exchange::sell_order((PAIR, 0)).sequence);
+		assert_eq!(<OrderSequence>::get(), 3);
+	});
+}
+
+#[test]
+fn mul_div_rounds_past_128_bits() {
+	new_test_ext().execute_with(|| {
+		let (numerator, denominator) = (1u128 << 126, 3u128 << 126);
+		assert_eq!(Exchange::mul_div(7, numerator, denominator, false), Some(2));
+		assert_eq!(Exchange::mul_div(7, numerator, denominator, true), Some(3));
+		assert_eq!(Exchange::mul_div(8, numerator, denominator, false), Some(2));
+		assert_eq!(Exchange::mul_div(8, numerator, denominator, true), Some(3));
+		assert_eq!(Exchange::mul_div(6, numerator, denominator, true), Some(2));
+		assert_eq!(Exchange::mul_div(6, numerator, 0, true), None);
+	});
+}
+
+#[test]
+fn reveal_returns_deposit_and_rests_order() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_order_commit(Origin::signed(ALICE), sealed(OrderSide::Sell, 1_000, Price::one())));
+		assert_eq!(Balances::free_balance(ALICE), 999_990);
+		assert_noop!(reveal(ALICE, 0, OrderSide::Sell, 1_000, Price::one()), Error::<Test>::RevealTooEarly);
+
+		run_to_block(2);
+		assert_noop!(reveal(ALICE, 0, OrderSide::Sell, 999, Price::one()), Error::<Test>::RevealMismatch);
+		assert_noop!(reveal(BOB, 0, OrderSide::Sell, 1_000, Price::one()), Error::<Test>::NotOrderOwner);
+		assert_ok!(reveal(ALICE, 0, OrderSide::Sell, 1_000, Price::one()));
+
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 999_000);
+		assert_eq!(Exchange::sell_order((PAIR, 0)).volume, 1_000);
+		assert!(<Commitments<Test>>::get(0).is_none());
+		assert_eq!(exchange_events().last(), Some(&RawEvent::CommitmentRevealed(ALICE, 0, Some(0))));
+	});
+}
+
+#[test]
+fn reveal_beyond_balance_keeps_commitment() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_order_commit(Origin::signed(ALICE), sealed(OrderSide::Sell, 2_000_000, Price::one())));
+		run_to_block(2);
+
+		assert_noop!(reveal(ALICE, 0, OrderSide::Sell, 2_000_000, Price::one()), Error::<Test>::InsufficientAmount);
+		assert!(<Commitments<Test>>::get(0).is_some());
+	});
+}
+
+#[test]
+fn unrevealed_commitment_forfeits_penalty() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_order_commit(Origin::signed(ALICE), sealed(OrderSide::Buy, 100, Price::one())));
+
+		run_to_block(7);
+		assert!(<Commitments<Test>>::get(0).is_some());
+		run_to_block(8);
+
+		assert!(<Commitments<Test>>::get(0).is_none());
+		assert_eq!(Balances::free_balance(ALICE), 999_999);
+		assert_eq!(Balances::free_balance(VAULT), 2);
+		assert_eq!(exchange_events().last(), Some(&RawEvent::CommitmentForfeited(ALICE, 0, 1, 9)));
+	});
+}
+
+#[test]
+fn commitment_sweep_has_its_own_bound() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_order_commit(Origin::signed(ALICE), sealed(OrderSide::Buy, 100, Price::one())));
+		assert_ok!(Exchange::exchange_order_commit(Origin::signed(BOB), sealed(OrderSide::Buy, 100, Price::one())));
+
+		run_to_block(8);
+		assert!(<Commitments<Test>>::get(0).is_none());
+		assert!(<Commitments<Test>>::get(1).is_some());
+
+		run_to_block(9);
+		assert!(<Commitments<Test>>::get(1).is_none());
+		assert_eq!(Balances::free_balance(BOB), 999_999);
+	});
+}
+
+#[test]
+fn expired_orders_are_refunded_and_carried_over() {
+	new_test_ext().execute_with(|| {
+		set_max_expiries(0);
+		for _ in 0..2 {
+			assert_ok!(Exchange::exchange_order_create_buy(
+				Origin::signed(ALICE), PAIR, 100, Price::one(), Some(3), TimeInForce::GoodTillCancelled, None));
+		}
+		assert_eq!(Balances::free_balance(ALICE), 999_800);
+
+		run_to_block(3);
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(<BuyOrder<Test>>::contains_key((PAIR, 1)));
+		assert_eq!(Balances::free_balance(ALICE), 999_900);
+
+		run_to_block(4);
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 1)));
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert_eq!(exchange_events().last(), Some(&RawEvent::OrderExpired(ALICE, PAIR, 1, OrderSide::Buy, 100, None)));
+	});
+}
+
+#[test]
+fn expiry_must_be_in_the_future() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			Exchange::exchange_order_create_buy(
+				Origin::signed(ALICE), PAIR, 100, Price::one(), Some(1), TimeInForce::GoodTillCancelled, None),
+			Error::<Test>::InvalidExpiry
+		);
+	});
+}
+
+#[test]
+fn client_id_stays_used_after_its_order_closes() {
+	new_test_ext().execute_with(|| {
+		let place = |who, client_id| Exchange::exchange_order_create_buy(
+			Origin::signed(who), PAIR, 100, Price::one(), None, TimeInForce::GoodTillCancelled, Some(client_id));
+		assert_ok!(place(ALICE, 7));
+		assert_noop!(place(ALICE, 7), Error::<Test>::DuplicateClientOrderId);
+		assert_ok!(place(BOB, 7));
+
+		assert_ok!(Exchange::exchange_order_cancel_by_client_id(Origin::signed(ALICE), 7));
+		assert_noop!(place(ALICE, 7), Error::<Test>::DuplicateClientOrderId);
+
+		// Once two newer ids are used, the closed order's id is forgotten.
+		assert_ok!(place(ALICE, 8));
+		assert_ok!(place(ALICE, 9));
+		assert_eq!(Exchange::used_client_order_ids(ALICE), vec![8, 9]);
+		assert_ok!(place(ALICE, 7));
+	});
+}
+
+#[test]
+fn immediate_order_uses_its_client_id() {
+	new_test_ext().execute_with(|| {
+		let place = || Exchange::exchange_order_create_buy(
+			Origin::signed(ALICE), PAIR, 100, Price::one(), None, TimeInForce::ImmediateOrCancel, Some(7));
+		assert_ok!(place());
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert_noop!(place(), Error::<Test>::DuplicateClientOrderId);
+	});
+}
+
+#[test]
+fn auction_pair_refuses_immediate_orders() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_create(Origin::root(), AssetId::Native, AssetId::Token(1), Some(ALICE)));
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Auction));
+		assert_ok!(sell(BOB, 1_000, Price::one()));
+
+		assert_noop!(
+			Exchange::exchange_order_create_buy(
+				Origin::signed(ALICE), PAIR, 100, Price::one(), None, TimeInForce::ImmediateOrCancel, None),
+			Error::<Test>::ImmediateOrderOnAuction
+		);
+		assert_noop!(
+			Exchange::exchange_order_market(Origin::signed(ALICE), PAIR, OrderSide::Buy, 100, Price::one(), true),
+			Error::<Test>::ImmediateOrderOnAuction
+		);
+		assert_noop!(
+			Exchange::exchange_order_route(Origin::signed(ALICE), AssetId::Native, vec![1, PAIR], 100, 0),
+			Error::<Test>::ImmediateOrderOnAuction
+		);
+		assert_noop!(
+			Exchange::exchange_conditional_create(Origin::signed(ALICE), PAIR, OrderSide::Buy, 100, Price::one(), None),
+			Error::<Test>::ImmediateOrderOnAuction
+		);
+		assert_ok!(Exchange::exchange_conditional_create(
+			Origin::signed(ALICE), PAIR, OrderSide::Buy, 100, Price::one(), Some(Price::one())));
+		assert_ok!(Exchange::exchange_order_create_buy(
+			Origin::signed(ALICE), PAIR, 100, Price::one(), None, TimeInForce::PostOnly, None));
+	});
+}
+
+#[test]
+fn market_stop_waits_while_pair_is_in_auction() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 100, Price::one()));
+		assert_ok!(sell(BOB, 100, Price::one()));
+		assert_ok!(Exchange::exchange_conditional_create(
+			Origin::signed(BOB), PAIR, OrderSide::Sell, 50, Price::one(), None));
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Auction));
+
+		Exchange::on_finalize(1);
+		assert!(Exchange::trades((PAIR, 0)).is_some());
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 0)).is_some());
+
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Batch));
+		Exchange::on_finalize(1);
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 0)).is_none());
+	});
+}
+
+#[test]
+fn triggering_resumes_from_cursor() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 100, Price::one()));
+		assert_ok!(sell(BOB, 100, Price::one()));
+		Exchange::on_finalize(1);
+
+		let half = Some(Price::saturating_from_rational(1, 2));
+		for trigger in [5, 1, 1].iter() {
+			assert_ok!(Exchange::exchange_conditional_create(
+				Origin::signed(ALICE), PAIR, OrderSide::Buy, 100, Price::saturating_from_integer(*trigger), half));
+		}
+
+		// Room to check two orders and release one.
+		set_matching_budget(2 + Exchange::match_step_weight());
+		Exchange::on_finalize(1);
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 1)).is_none());
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 2)).is_some());
+		assert!(<TriggerCursor>::get().is_some());
+
+		Exchange::on_finalize(1);
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 0)).is_some());
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 2)).is_none());
+		assert!(<TriggerCursor>::get().is_none());
+	});
+}
 
 #[test]
-fn it_works_for_default_value() {
+fn order_weights_cover_matching() {
 	new_test_ext().execute_with(|| {
-		// Dispatch a signed extrinsic.
-		assert_ok!(TemplateModule::do_something(Origin::signed(1), 42));
-		// Read pallet storage and assert an expected result.
-		assert_eq!(TemplateModule::something(), Some(42));
+		let fills = Exchange::match_step_weight() * MaxFillsPerOrder::get() as Weight;
+		let create = Call::<Test>::exchange_order_create_buy(
+			PAIR, 100, Price::one(), None, TimeInForce::FillOrKill, None);
+		assert!(create.get_dispatch_info().weight >= 2 * fills);
+		let market = Call::<Test>::exchange_order_market(PAIR, OrderSide::Buy, 100, Price::one(), false);
+		assert!(market.get_dispatch_info().weight >= 2 * fills);
+
+		let route = |hops: u128| Call::<Test>::exchange_order_route(AssetId::Native, (0..hops).collect(), 100, 0)
+			.get_dispatch_info().weight;
+		assert!(route(1) >= fills);
+		assert_eq!(route(3), 3 * route(1));
+	});
+}
+
+#[test]
+fn iceberg_matches_on_continuous_pair() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Continuous));
+		assert_ok!(sell(BOB, 300, Price::one()));
+		assert_ok!(Exchange::exchange_order_create_iceberg(
+			Origin::signed(ALICE), PAIR, OrderSide::Buy, 1_000, 100, Price::one(), None));
+
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		let order = Exchange::buy_order((PAIR, 0));
+		assert_eq!((order.volume, order.display, order.reserve), (100, 100, 600));
+	});
+}
+
+#[test]
+fn continuous_pair_refuses_placements_that_can_not_match() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Continuous));
+		assert_ok!(sell(BOB, 100, Price::saturating_from_integer(2)));
+		assert_ok!(buy(ALICE, 100, Price::one()));
+
+		assert_noop!(
+			Exchange::exchange_order_amend(
+				Origin::signed(ALICE), PAIR, OrderSide::Buy, 0, None, Some(Price::saturating_from_integer(2))),
+			Error::<Test>::WouldCrossBook
+		);
+		assert_noop!(
+			Exchange::exchange_order_bracket(
+				Origin::signed(ALICE), PAIR, OrderSide::Sell, 100,
+				Price::one(), Price::saturating_from_rational(1, 2), None, GroupPolicy::AnyFill),
+			Error::<Test>::WouldCrossBook
+		);
+	});
+}
+
+#[test]
+fn stop_limit_matches_on_release_on_continuous_pair() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Continuous));
+		assert_ok!(sell(BOB, 100, Price::one()));
+		assert_ok!(buy(ALICE, 100, Price::one()));
+		assert_ok!(sell(BOB, 500, Price::one()));
+		assert_ok!(Exchange::exchange_conditional_create(
+			Origin::signed(ALICE), PAIR, OrderSide::Buy, 100, Price::one(), Some(Price::one())));
+
+		Exchange::on_finalize(1);
+
+		assert_eq!(Exchange::sell_order((PAIR, 1)).volume, 400);
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(exchange_events().contains(&RawEvent::ConditionalOrderConverted(PAIR, 0, None)));
+	});
+}
+
+#[test]
+fn time_in_force_rejections_and_refunds() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(sell(BOB, 500, Price::one()));
+		let place = |time_in_force| Exchange::exchange_order_create_buy(
+			Origin::signed(ALICE), PAIR, 1_000, Price::one(), None, time_in_force, None);
+
+		assert_noop!(place(TimeInForce::PostOnly), Error::<Test>::WouldCrossBook);
+		assert_noop!(place(TimeInForce::FillOrKill), Error::<Test>::CannotFillOrKill);
+		assert_ok!(place(TimeInForce::ImmediateOrCancel));
+
+		// Half fills against the resting sell and the rest is refunded.
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert_eq!(Balances::free_balance(ALICE), 999_500);
+		assert_eq!(
+			exchange_events().last(),
+			Some(&RawEvent::ImmediateOrderClosed(ALICE, PAIR, OrderSide::Buy, 500, 500, None)));
+	});
+}
+
+#[test]
+fn market_order_respects_slippage() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(sell(BOB, 500, Price::one()));
+		assert_ok!(sell(BOB, 500, Price::saturating_from_integer(2)));
+		let market = |volume, worst_ratio, allow_partial| Exchange::exchange_order_market(
+			Origin::signed(ALICE), PAIR, OrderSide::Buy, volume, worst_ratio, allow_partial);
+
+		assert_noop!(market(1_000, Price::one(), false), Error::<Test>::SlippageExceeded);
+		assert_noop!(market(5_000, Price::saturating_from_integer(2), false), Error::<Test>::InsufficientLiquidity);
+
+		// Only the level within the worst ratio is taken; the rest is refunded.
+		assert_ok!(market(1_000, Price::one(), true));
+		assert_eq!(Balances::free_balance(ALICE), 999_500);
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		assert_eq!(Exchange::sell_order((PAIR, 1)).volume, 500);
+	});
+}
+
+#[test]
+fn stop_loss_triggers_on_last_trade_price() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_ok!(sell(BOB, 500, Price::one()));
+		assert_ok!(Exchange::exchange_conditional_create(
+			Origin::signed(BOB), PAIR, OrderSide::Sell, 200, Price::one(), None));
+		assert_ok!(Exchange::exchange_conditional_create(
+			Origin::signed(BOB), PAIR, OrderSide::Sell, 100, Price::saturating_from_rational(1, 2), None));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &BOB), 999_200);
+
+		Exchange::on_finalize(1);
+
+		// The trade at 1 triggers the first stop, which sells into the rest of the bid.
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 0)).is_none());
+		assert!(<ConditionalOrder<Test>>::get((PAIR, 1)).is_some());
+		assert_eq!(Exchange::buy_order((PAIR, 0)).volume, 300);
+		assert!(exchange_events().contains(&RawEvent::ConditionalOrderConverted(PAIR, 0, None)));
+
+		assert_ok!(Exchange::exchange_conditional_cancel(Origin::signed(BOB), PAIR, 1));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &BOB), 999_300);
+	});
+}
+
+#[test]
+fn iceberg_replenishes_from_reserve() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_order_create_iceberg(
+			Origin::signed(ALICE), PAIR, OrderSide::Sell, 1_000, 100, Price::one(), None));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 999_000);
+		assert_ok!(buy(BOB, 150, Price::one()));
+
+		Exchange::on_finalize(1);
+
+		// The first slice fills, the next is refilled and half of it fills too.
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		let order = Exchange::sell_order((PAIR, 0));
+		assert_eq!((order.volume, order.display, order.reserve), (50, 100, 800));
+		assert!(exchange_events().contains(&RawEvent::IcebergReplenished(PAIR, OrderSide::Sell, 0, 100)));
+	});
+}
+
+#[test]
+fn account_orders_follow_open_orders() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 1_000, Price::one()));
+		assert_ok!(sell(ALICE, 100, Price::saturating_from_integer(2)));
+		let ask = OrderRef { side: OrderSide::Sell, pair: PAIR, order_id: 0 };
+		assert_eq!(
+			Exchange::account_orders(ALICE),
+			vec![OrderRef { side: OrderSide::Buy, pair: PAIR, order_id: 0 }, ask.clone()]);
+
+		assert_ok!(Exchange::exchange_order_cancel_buy(Origin::signed(ALICE), PAIR, 0));
+		assert_eq!(Exchange::account_orders(ALICE), vec![ask]);
+
+		assert_ok!(buy(BOB, 200, Price::saturating_from_integer(2)));
+		assert_eq!(Exchange::account_orders(BOB).len(), 1);
+		Exchange::on_finalize(1);
+		assert!(Exchange::account_orders(ALICE).is_empty());
+		assert!(Exchange::account_orders(BOB).is_empty());
+	});
+}
+
+#[test]
+fn matching_suspends_when_budget_runs_out() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(ALICE, 100, Price::one()));
+		assert_ok!(buy(ALICE, 100, Price::one()));
+		assert_ok!(sell(BOB, 200, Price::one()));
+
+		set_matching_budget(Exchange::match_step_weight());
+		assert!(Exchange::on_initialize(1) >= Exchange::match_step_weight());
+		Exchange::on_finalize(1);
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(<BuyOrder<Test>>::contains_key((PAIR, 1)));
+		assert!(<MatchCursor>::get().is_some());
+		assert!(exchange_events().contains(&RawEvent::MatchingSuspended(PAIR)));
+
+		Exchange::on_finalize(2);
+		assert!(!<BuyOrder<Test>>::contains_key((PAIR, 1)));
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		assert!(<MatchCursor>::get().is_none());
+	});
+}
+
+#[test]
+fn market_params_reject_bad_orders() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_market_params(Origin::root(), PAIR, Price::one(), 10, 50));
+		assert_eq!(
+			exchange_events().last(),
+			Some(&RawEvent::PairMarketParamsSet(PAIR, Price::one(), 10, 50)));
+
+		assert_noop!(buy(ALICE, 300, Price::saturating_from_rational(3, 2)), Error::<Test>::InvalidTickSize);
+		assert_noop!(sell(BOB, 15, Price::one()), Error::<Test>::InvalidLotSize);
+		// The lot size applies to the target a buy would receive.
+		assert_noop!(buy(ALICE, 150, Price::saturating_from_integer(2)), Error::<Test>::InvalidLotSize);
+		assert_noop!(sell(BOB, 40, Price::one()), Error::<Test>::BelowMinimumNotional);
+
+		assert_ok!(sell(BOB, 60, Price::one()));
+		assert_ok!(buy(ALICE, 200, Price::saturating_from_integer(2)));
+	});
+}
+
+#[test]
+fn clearing_price_tie_rules() {
+	new_test_ext().execute_with(|| {
+		let order = |side, volume: u64, ratio: u128| (
+			OrderRef { side, pair: PAIR, order_id: 0 }, volume, Price::saturating_from_integer(ratio));
+		let bid = |volume, ratio| order(OrderSide::Buy, volume, ratio);
+		let ask = |volume, ratio| order(OrderSide::Sell, volume, ratio);
+
+		// Most executed volume first.
+		assert_eq!(Exchange::clearing_price(&[bid(100, 2)], &[ask(100, 1)]), Some(Price::one()));
+		// Then the smallest surplus.
+		assert_eq!(Exchange::clearing_price(&[bid(100, 2)], &[ask(50, 1)]), Some(Price::saturating_from_integer(2)));
+		// Then the lowest price.
+		assert_eq!(Exchange::clearing_price(&[bid(200, 2)], &[ask(100, 1), ask(100, 2)]), Some(Price::one()));
+		assert_eq!(Exchange::clearing_price(&[], &[ask(100, 1)]), None);
+	});
+}
+
+#[test]
+fn auction_fills_crossing_orders_at_one_price() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_matching_mode(Origin::root(), PAIR, MatchingMode::Auction));
+		assert_ok!(buy(ALICE, 200, Price::saturating_from_integer(2)));
+		assert_ok!(sell(BOB, 100, Price::one()));
+		assert_ok!(sell(BOB, 100, Price::saturating_from_integer(2)));
+
+		Exchange::on_finalize(1);
+
+		assert!(exchange_events().contains(&RawEvent::AuctionCleared(PAIR, Price::one(), 100, 100)));
+		assert_eq!(Exchange::trades((PAIR, 0)).map(|trade| trade.ratio), Some(Price::one()));
+		assert_eq!(Exchange::buy_order((PAIR, 0)).volume, 100);
+		assert!(!<SellOrder<Test>>::contains_key((PAIR, 0)));
+		assert_eq!(Exchange::sell_order((PAIR, 1)).volume, 100);
+	});
+}
+
+#[test]
+fn quote_previews_fill_without_writing() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(sell(BOB, 1_000, Price::one()));
+		assert_ok!(sell(BOB, 1_000, Price::saturating_from_integer(2)));
+
+		assert_eq!(Exchange::quote(PAIR, OrderSide::Buy, 3_000, None), Quote {
+			filled: 3_000,
+			received: 1_998,
+			average_price: Price::saturating_from_rational(3, 2),
+			fee: 2,
+			unfilled: 0,
+		});
+		assert_eq!(Exchange::quote(PAIR, OrderSide::Buy, 3_000, Some(Price::one())), Quote {
+			filled: 1_000,
+			received: 999,
+			average_price: Price::one(),
+			fee: 1,
+			unfilled: 2_000,
+		});
+
+		assert_eq!(Exchange::sell_order((PAIR, 0)).volume, 1_000);
+		assert_eq!(Exchange::trade_count(PAIR), 0);
+		assert_eq!(Exchange::order_book_depth(OrderSide::Sell, PAIR), vec![
+			(Price::one(), 1_000),
+			(Price::saturating_from_integer(2), 1_000),
+		]);
+	});
+}
+
+#[test]
+fn route_rolls_back_below_min_output() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_create(Origin::root(), AssetId::Native, AssetId::Token(1), Some(ALICE)));
+		mint(1, BOB, 1_000_000);
+		assert_ok!(buy(BOB, 1_000, Price::one()));
+		assert_ok!(Exchange::exchange_order_create_sell(
+			Origin::signed(BOB), 1, 1_000, Price::one(), None, TimeInForce::GoodTillCancelled, None));
+		let route = |min_output| Exchange::exchange_order_route(
+			Origin::signed(ALICE), AssetId::Token(0), vec![PAIR, 1], 1_000, min_output);
+
+		// 1000 token 0 buys 999 native, which buy 998 token 1 after both fees.
+		assert_noop!(route(999), Error::<Test>::SlippageExceeded);
+		assert_ok!(route(998));
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &ALICE), 999_000);
+		assert_eq!(Exchange::asset_balance(AssetId::Token(1), &ALICE), 998);
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert_eq!(
+			exchange_events().last(),
+			Some(&RawEvent::RouteExecuted(ALICE, AssetId::Token(0), AssetId::Token(1), 1_000, 998)));
+	});
+}
+
+#[test]
+fn delist_refunds_in_chunks_then_removes_pair() {
+	new_test_ext().execute_with(|| {
+		for _ in 0..6 {
+			assert_ok!(buy(ALICE, 100, Price::one()));
+			assert_ok!(sell(BOB, 100, Price::saturating_from_integer(2)));
+		}
+		assert_ok!(Exchange::exchange_pair_delist(Origin::root(), PAIR));
+		assert_noop!(buy(ALICE, 100, Price::one()), Error::<Test>::TradingPairPaused);
+		assert_noop!(Exchange::exchange_pair_resume(Origin::root(), PAIR), Error::<Test>::PairDelisting);
+
+		// `MaxDelistRefundsPerBlock` is 10: every buy and four of the sells.
+		Exchange::on_initialize(2);
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &BOB), 999_800);
+		assert!(Exchange::pair(PAIR).is_some());
+		assert_eq!(Exchange::pair_delisting(), vec![PAIR]);
+
+		Exchange::on_initialize(3);
+		assert_eq!(Exchange::asset_balance(AssetId::Token(0), &BOB), 1_000_000);
+		assert!(Exchange::pair(PAIR).is_none());
+		assert!(Exchange::pair_of((AssetId::Native, AssetId::Token(0))).is_none());
+		assert!(Exchange::pair_delisting().is_empty());
+		assert_eq!(exchange_events().last(), Some(&RawEvent::PairDelisted(PAIR)));
+	});
+}
+
+#[test]
+fn pair_create_rejects_duplicates_and_self_pairs() {
+	new_test_ext().execute_with(|| {
+		let create = |base, target| Exchange::exchange_pair_create(Origin::root(), base, target, Some(ALICE));
+
+		assert_eq!(Exchange::pair_of((AssetId::Native, AssetId::Token(0))), Some(PAIR));
+		assert_noop!(create(AssetId::Native, AssetId::Token(0)), Error::<Test>::PairExists);
+		assert_noop!(create(AssetId::Token(1), AssetId::Token(1)), Error::<Test>::IdenticalAssets);
+
+		// The reverse direction is a market of its own.
+		assert_ok!(create(AssetId::Token(0), AssetId::Native));
+		assert_eq!(Exchange::pair_of((AssetId::Token(0), AssetId::Native)), Some(1));
 	});
 }
 
 #[test]
-fn correct_error_for_none_value() {
+fn pair_create_checks_assets_and_keeps_metadata() {
 	new_test_ext().execute_with(|| {
-		// Ensure the expected error is thrown when no value is present.
+		let create = |target| Exchange::exchange_pair_create(Origin::root(), AssetId::Native, target, Some(ALICE));
+
+		assert_noop!(create(AssetId::Token(3)), Error::<Test>::UnknownAsset);
+		assert_noop!(create(AssetId::Token(2)), Error::<Test>::AssetDisabled);
+
+		let info = Exchange::pair(PAIR).unwrap();
+		assert_eq!(info.base_metadata, AssetMetadata { decimals: 12, symbol: b"DCB".to_vec() });
+		assert_eq!(info.target_metadata, AssetMetadata { decimals: 6, symbol: b"T0".to_vec() });
+	});
+}
+
+#[test]
+fn admin_calls_check_their_origins() {
+	new_test_ext().execute_with(|| {
+		let user = || Origin::signed(ALICE);
+		assert_noop!(
+			Exchange::exchange_pair_create(user(), AssetId::Native, AssetId::Token(1), None), BadOrigin);
+		assert_noop!(Exchange::exchange_pair_market_params(user(), PAIR, Price::one(), 1, 1), BadOrigin);
+		assert_noop!(Exchange::exchange_pair_pause(user(), PAIR), BadOrigin);
+		assert_noop!(Exchange::exchange_pair_delist(user(), PAIR), BadOrigin);
+		assert_noop!(Exchange::exchange_accounts(user(), 1, ALICE), BadOrigin);
+
+		// Root is not signed, so it has to name the banker.
 		assert_noop!(
-			TemplateModule::cause_error(Origin::signed(1)),
-			Error::<Test>::NoneValue
+			Exchange::exchange_pair_create(Origin::root(), AssetId::Native, AssetId::Token(1), None),
+			Error::<Test>::NoBanker
 		);
+		assert_ok!(Exchange::exchange_pair_create(Origin::root(), AssetId::Native, AssetId::Token(1), Some(BOB)));
+		assert_eq!(Exchange::pair(1).map(|info| info.banker), Some(BOB));
+	});
+}
+
+#[test]
+fn listing_bond_is_returned_or_slashed() {
+	new_test_ext().execute_with(|| {
+		let propose = |who, base, target| Exchange::exchange_pair_propose(Origin::signed(who), base, target);
+		assert_noop!(propose(ALICE, AssetId::Native, AssetId::Token(0)), Error::<Test>::PairExists);
+		assert_ok!(propose(ALICE, AssetId::Native, AssetId::Token(1)));
+		assert_ok!(propose(BOB, AssetId::Native, AssetId::Token(1)));
+		assert_ok!(propose(BOB, AssetId::Token(0), AssetId::Token(1)));
+		assert_eq!(Balances::reserved_balance(ALICE), 100);
+		assert_eq!(Balances::reserved_balance(BOB), 200);
+		assert_eq!(Exchange::pending_listings().len(), 3);
+
+		assert_noop!(Exchange::exchange_pair_approve(Origin::signed(ALICE), 0), BadOrigin);
+		assert_ok!(Exchange::exchange_pair_approve(Origin::root(), 0));
+		assert_eq!(Balances::free_balance(ALICE), 1_000_000);
+		assert_eq!(Exchange::pair_of((AssetId::Native, AssetId::Token(1))), Some(1));
+		assert_eq!(Exchange::pair(1).map(|info| info.banker), Some(ALICE));
+		assert_eq!(exchange_events().last(), Some(&RawEvent::ListingApproved(0, 1)));
+
+		// The second proposal now duplicates a listed pair.
+		assert_noop!(Exchange::exchange_pair_approve(Origin::root(), 1), Error::<Test>::PairExists);
+		assert_ok!(Exchange::exchange_pair_reject(Origin::root(), 1, true));
+		assert_eq!(Balances::free_balance(VAULT), 101);
+		assert_ok!(Exchange::exchange_pair_reject(Origin::root(), 2, false));
+		assert_eq!(Balances::reserved_balance(BOB), 0);
+		assert_eq!(Balances::free_balance(BOB), 999_900);
+		assert!(Exchange::pending_listings().is_empty());
+		assert_noop!(Exchange::exchange_pair_reject(Origin::root(), 2, false), Error::<Test>::ListingNotFound);
+	});
+}
+
+#[test]
+fn genesis_sets_storage_version() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(<StorageVersion>::get(), STORAGE_VERSION);
+	});
+}
+
+
+#[test]
+fn token_pair_escrows_tokens() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Exchange::exchange_pair_create(Origin::root(), AssetId::Token(0), AssetId::Token(1), Some(ALICE)));
+		mint(1, BOB, 1_000);
+		assert_ok!(Exchange::exchange_order_create_sell(
+			Origin::signed(BOB), 1, 400, Price::one(), None, TimeInForce::GoodTillCancelled, None));
+
+		assert_eq!(Exchange::asset_balance(AssetId::Token(1), &BOB), 600);
+		assert_eq!(Exchange::asset_balance(AssetId::Token(1), &OPERATION), 400);
+		assert_eq!(Balances::free_balance(BOB), 1_000_000);
+	});
+}
+
+#[test]
+fn escrow_beyond_balance_fails() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(sell(ALICE, 2_000_000, Price::one()), Error::<Test>::InsufficientAmount);
+		assert_noop!(
+			buy(ALICE, 2_000_000, Price::one()),
+			balances::Error::<Test, balances::DefaultInstance>::InsufficientBalance
+		);
+	});
+}
+
+#[test]
+fn migrates_first_release_storage() {
+	new_legacy_ext().execute_with(|| {
+		<StorageVersion>::put(0);
+		put_legacy(b"Pair", 0u128, legacy_pair_v0());
+		<PairCount>::put(1);
+		put_legacy(b"BuyOrder", (0u128, 0u128), LegacyOrderInfoV0::<u64, u64, u64> {
+			order_id: 0, pair: 0, owner: ALICE, volume: 1_000, ratio: 2_000_000_000_000, created: 1
+		});
+		put_legacy(b"BuyOrderList", 0u128, vec![0u128]);
+		put_legacy(b"Trades", (0u128, 0u128), LegacyTradeInfo::<u64, u64, u64> {
+			pair: 0, seller: BOB, buyer: ALICE, volume: 10, ratio: 500_000_000_000, created: 1
+		});
+
+		Exchange::on_runtime_upgrade();
+
+		let two = Price::saturating_from_integer(2);
+		let order = Exchange::buy_order((0, 0));
+		assert_eq!((order.buyer, order.volume, order.ratio), (ALICE, 1_000, two));
+		assert_eq!((order.display, order.reserve, order.expires_at, order.client_id), (0, 0, None, None));
+		assert_eq!(Exchange::price_levels((OrderSide::Buy, 0)), vec![two]);
+		assert_eq!(<LevelOrders>::get((OrderSide::Buy, 0, two)), vec![0]);
+		assert_eq!(
+			migration::get_storage_value::<Vec<u128>>(b"Exchange", b"BuyOrderList", &Blake2_128Concat::hash(&0u128.encode())),
+			None);
+		assert_eq!(<Trades<Test>>::get((0, 0)).map(|t| t.ratio), Some(Price::saturating_from_rational(1, 2)));
+		assert_eq!(<StorageVersion>::get(), STORAGE_VERSION);
+	});
+}
+
+
+#[test]
+fn migrates_orders_to_price_levels() {
+	new_legacy_ext().execute_with(|| {
+		<StorageVersion>::put(3);
+		for (order_id, ratio) in [(0u128, 1u64), (1, 3), (2, 1)].iter() {
+			put_legacy(b"BuyOrder", (0u128, *order_id), LegacyOrderInfo::<u64, u64, u64, u64> {
+				order_id: *order_id, pair: 0, owner: ALICE, volume: 100, ratio: ratio * 1_000_000_000_000,
+				display: 0, reserve: 0, created: 1, expires_at: None, client_id: None
+			});
+		}
+
+		Exchange::on_runtime_upgrade();
+
+		let (one, three) = (Price::one(), Price::saturating_from_integer(3));
+		assert_eq!(Exchange::price_levels((OrderSide::Buy, 0)), vec![three, one]);
+		assert_eq!(<LevelOrders>::get((OrderSide::Buy, 0, one)), vec![0, 2]);
+		assert_eq!(<LevelOrders>::get((OrderSide::Buy, 0, three)), vec![1]);
+	});
+}
+
+#[test]
+fn migrates_pairs_to_metadata_layout() {
+	new_legacy_ext().execute_with(|| {
+		<StorageVersion>::put(5);
+		put_legacy(b"Pair", 0u128, legacy_pair_v0());
+		<PairCount>::put(1);
+
+		Exchange::on_runtime_upgrade();
+
+		let pair = <Pair<Test>>::get(0).unwrap();
+		assert_eq!((pair.base, pair.target, pair.banker), (AssetId::Token(0), AssetId::Token(1), ALICE));
+		assert_eq!(pair.lot_size, 0);
+		assert_eq!(pair.target_metadata.decimals, 6);
+		assert_eq!(<PairOf>::get((AssetId::Token(0), AssetId::Token(1))), Some(0));
+	});
+}
+
+#[test]
+fn migrates_native_pairs_after_token_pairs() {
+	new_legacy_ext().execute_with(|| {
+		<StorageVersion>::put(6);
+		put_legacy(b"Pair", 0u128, LegacyPairInfo::<u64, u64, u64> {
+			base: 0, target: 1, banker: ALICE, active: true, created: 1,
+			tick_size: Price::zero(), lot_size: 0, min_notional: 0
+		});
+		<PairCount>::put(1);
+		put_legacy(b"PairNative", 0u128, LegacyPairNativeInfo::<u64, u64, u64> {
+			target: 1, banker: BOB, active: true, created: 1,
+			tick_size: Price::zero(), lot_size: 0, min_notional: 0
+		});
+		migration::put_storage_value(b"Exchange", b"PairNativeCount", &[], 1u128);
+
+		let two = Price::saturating_from_integer(2);
+		put_legacy(b"SellOrderNative", (0u128, 0u128), LegacyOrderInfo::<u64, u64, u64, Price> {
+			order_id: 0, pair: 0, owner: BOB, volume: 100, ratio: two,
+			display: 0, reserve: 0, created: 1, expires_at: None, client_id: None
+		});
+		put_legacy(b"SellOrderNativeCount", 0u128, 1u128);
+		put_legacy(b"PriceLevels", (BookKind::Native, OrderSide::Sell, 0u128), vec![two]);
+		put_legacy(b"LevelOrders", (BookKind::Native, OrderSide::Sell, 0u128, two), vec![0u128]);
+
+		Exchange::on_runtime_upgrade();
+
+		assert_eq!(<PairCount>::get(), 2);
+		let pair = <Pair<Test>>::get(1).unwrap();
+		assert_eq!((pair.base, pair.target, pair.banker), (AssetId::Native, AssetId::Token(1), BOB));
+		assert_eq!(<PairOf>::get((AssetId::Native, AssetId::Token(1))), Some(1));
+		assert_eq!(<PairOf>::get((AssetId::Token(0), AssetId::Token(1))), Some(0));
+
+		let order = <SellOrder<Test>>::get((1, 0));
+		assert_eq!((order.pair, order.seller, order.volume), (1, BOB, 100));
+		assert_eq!(<SellOrderCount>::get(1), 1);
+		assert_eq!(Exchange::price_levels((OrderSide::Sell, 1)), vec![two]);
+		assert_eq!(<LevelOrders>::get((OrderSide::Sell, 1, two)), vec![0]);
+	});
+}
+
+#[test]
+fn migrates_orders_to_arrival_sequence() {
+	new_legacy_ext().execute_with(|| {
+		<StorageVersion>::put(9);
+		let order = |order_id, created| LegacyOrderInfo::<u64, u64, u64, Price> {
+			order_id, pair: 0, owner: ALICE, volume: 100, ratio: Price::one(),
+			display: 0, reserve: 0, created, expires_at: None, client_id: None
+		};
+		put_legacy(b"BuyOrder", (0u128, 0u128), order(0, 2));
+		put_legacy(b"BuyOrder", (0u128, 1u128), order(1, 3));
+		put_legacy(b"SellOrder", (0u128, 0u128), order(0, 2));
+		put_legacy(b"SellOrder", (0u128, 1u128), order(1, 1));
+
+		Exchange::on_runtime_upgrade();
+
+		assert_eq!(Exchange::sell_order((0, 1)).sequence, 0);
+		assert_eq!(Exchange::buy_order((0, 0)).sequence, 1);
+		assert_eq!(Exchange::sell_order((0, 0)).sequence, 2);
+		assert_eq!(Exchange::buy_order((0, 1)).sequence, 3);
+		assert_eq!(Exchange::buy_order((0, 1)).buyer, ALICE);
+		assert_eq!(<OrderSequence>::get(), 4);
+		assert_eq!(<StorageVersion>::get(), STORAGE_VERSION);
 	});
 }